@@ -1,11 +1,86 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    /// Output format for the status summary
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub filter: FilterArgs,
+
+    /// Maximum number of commands to run at once in parallel (0 for no limit)
+    #[arg(short = 'j', long = "jobs", value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Kill parallel commands that run for longer than this, e.g. '30s' or '2m'
+    #[arg(long = "timeout", value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
+}
+
+/// Filters narrowing the repositories for a single invocation. These don't change the context.
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Only operate on repositories with this tag (can be repeated)
+    #[arg(long = "tag", value_name = "TAG")]
+    pub tags: Vec<String>,
+
+    /// Only operate on repositories matching this pattern (can be repeated)
+    #[arg(long = "only", value_name = "PATTERN")]
+    pub only: Vec<String>,
+
+    /// Don't operate on repositories matching this pattern (can be repeated)
+    #[arg(long = "exclude", value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Only operate on repositories matching a predicate, e.g. 'behind or dirty' (can be
+    /// repeated)
+    #[arg(long = "where", value_name = "PREDICATE")]
+    pub predicates: Vec<String>,
+
+    /// Only operate on repositories with local modifications
+    #[arg(long = "dirty")]
+    pub dirty: bool,
+}
+
+/// What to do when a command run in each repository in turn fails.
+#[derive(Args)]
+pub struct SerialArgs {
+    /// Stop at the first repository where the command fails
+    #[arg(long = "fail-fast", overrides_with = "keep_going")]
+    pub fail_fast: bool,
+
+    /// Carry on after a failure and report it at the end (the default)
+    #[arg(long = "keep-going", overrides_with = "fail_fast")]
+    pub keep_going: bool,
+
+    /// Restart the last failed run of the same command from the repository where it stopped
+    #[arg(long = "resume")]
+    pub resume: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// A table for display in the terminal
+    Table,
+    /// A JSON array of repositories
+    Json,
+    /// Newline delimited JSON, one object per repository
+    Ndjson,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+pub enum ManifestFormat {
+    /// TOML, as read by 'clone --manifest'
+    Toml,
+    Json,
+    Yaml,
 }
 
 #[derive(ValueEnum, Clone)]
@@ -22,6 +97,78 @@ pub enum Commands {
         /// Path to register
         #[arg(value_name = "PATH")]
         path: PathBuf,
+
+        /// Only look this many directories deep for repositories
+        #[arg(long = "max-depth", value_name = "N")]
+        max_depth: Option<usize>,
+
+        /// Skip directories matching this glob, as in .gitlassoignore (can be repeated)
+        #[arg(long = "exclude", value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Also look for repositories nested inside other repositories
+        #[arg(long = "nested")]
+        nested: bool,
+
+        /// Also register the submodules of each repository
+        #[arg(long = "submodules")]
+        submodules: bool,
+
+        /// Show what would be registered without registering anything
+        #[arg(long = "dry-run", conflicts_with = "interactive")]
+        dry_run: bool,
+
+        /// Choose which of the discovered repositories to register
+        #[arg(short = 'i', long = "interactive")]
+        interactive: bool,
+
+        /// Find registered repositories that have moved here, by remote URL and first commit
+        #[arg(long = "rescan")]
+        rescan: bool,
+    },
+
+    /// Clone the repositories listed in a manifest and register them
+    Clone {
+        /// A TOML file listing the repositories to clone
+        #[arg(long = "manifest", value_name = "FILE")]
+        manifest: PathBuf,
+
+        /// The directory to clone into (defaults to the current directory)
+        #[arg(long = "root", value_name = "DIR")]
+        root: Option<PathBuf>,
+    },
+
+    /// Write a manifest of the repositories in the current context, for use with 'clone'
+    Export {
+        /// The format of the manifest
+        #[arg(long = "manifest-format", value_enum, default_value_t = ManifestFormat::Toml)]
+        manifest_format: ManifestFormat,
+
+        /// Write paths relative to this directory (defaults to the current directory)
+        #[arg(long = "root", value_name = "DIR")]
+        root: Option<PathBuf>,
+
+        /// Write the manifest to a file rather than printing it
+        #[arg(short = 'o', long = "output", value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Change the paths of registered repositories after moving the directory they are in
+    Relocate {
+        /// The directory the repositories were in
+        #[arg(value_name = "OLD_PREFIX")]
+        old_prefix: PathBuf,
+
+        /// The directory the repositories are in now
+        #[arg(value_name = "NEW_PREFIX")]
+        new_prefix: PathBuf,
+    },
+
+    /// Unregister repositories that are missing or are no longer git repositories
+    Prune {
+        /// Show what would be unregistered without unregistering anything
+        #[arg(long = "dry-run")]
+        dry_run: bool,
     },
 
     /// Unregister all repositories in the current context
@@ -39,6 +186,9 @@ pub enum Commands {
 
     /// Exec a git command on all repositories
     Git {
+        #[command(flatten)]
+        serial: SerialArgs,
+
         #[arg(trailing_var_arg = true)]
         args: Vec<String>,
     },
@@ -46,15 +196,44 @@ pub enum Commands {
     /// Execute a command on all repositories
     Exec {
         /// Run command in parallel
-        #[arg(short = 'p')]
+        #[arg(short = 'p', conflicts_with_all = ["fail_fast", "keep_going", "resume"])]
         parallel: bool,
 
+        /// Label each line of parallel output with the stream it was written to
+        #[arg(long = "labels", requires = "parallel")]
+        label_streams: bool,
+
+        /// Print parallel output line by line as it arrives, prefixed by the repository name
+        #[arg(long = "stream", requires = "parallel")]
+        stream: bool,
+
+        #[command(flatten)]
+        serial: SerialArgs,
+
+        /// Run a script with the shell in each repository, e.g. 'make && make test'
+        #[arg(long = "shell", value_name = "SCRIPT", conflicts_with = "args")]
+        shell: Option<String>,
+
         #[arg(last = true)]
         args: Vec<String>,
     },
 
     /// Select which repositories commands will apply to
-    Context,
+    #[command(args_conflicts_with_subcommands = true)]
+    Context {
+        /// Select the repositories with this tag (can be repeated)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+
+        #[command(subcommand)]
+        command: Option<ContextCommands>,
+    },
+
+    /// Manage repository tags
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
 
     /// Print completions for various shells
     Completions {
@@ -65,3 +244,92 @@ pub enum Commands {
         binary_name: String,
     },
 }
+
+#[derive(Subcommand)]
+pub enum ContextCommands {
+    /// Save the current context under a name
+    Save {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+
+    /// Switch to a saved context
+    Use {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+
+    /// List saved contexts
+    List,
+
+    /// Set the context to the repositories matching any pattern
+    ///
+    /// Patterns match a repository name or alias (e.g. 'api-*'), a path glob if they contain a
+    /// '/' (e.g. '~/src/payments/*'), or a regular expression if prefixed with 're:'.
+    Set {
+        #[arg(value_name = "PATTERN", required = true)]
+        patterns: Vec<String>,
+    },
+
+    /// Add the repositories matching any pattern to the context
+    Add {
+        #[arg(value_name = "PATTERN", required = true)]
+        patterns: Vec<String>,
+    },
+
+    /// Remove the repositories matching any pattern from the context
+    Remove {
+        #[arg(value_name = "PATTERN", required = true)]
+        patterns: Vec<String>,
+    },
+
+    /// Set the context to the repositories matching a predicate on their git state
+    ///
+    /// Conditions are dirty, clean, ahead, behind, no-upstream and branch=<glob>. They can be
+    /// negated with 'not' and combined with 'and' and 'or', e.g. 'dirty or behind'.
+    Where {
+        #[arg(value_name = "PREDICATE", required = true)]
+        predicate: Vec<String>,
+    },
+
+    /// Select all repositories
+    All,
+
+    /// Deselect all repositories
+    None,
+
+    /// Select exactly the repositories that are not selected
+    Invert,
+
+    /// Delete a saved context
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TagCommands {
+    /// Add a tag to repositories
+    Add {
+        #[arg(value_name = "TAG")]
+        tag: String,
+
+        /// Repositories to tag, by pattern (defaults to the current context)
+        #[arg(value_name = "REPO")]
+        repos: Vec<String>,
+    },
+
+    /// Remove a tag from repositories
+    Remove {
+        #[arg(value_name = "TAG")]
+        tag: String,
+
+        /// Repositories to untag, by pattern (defaults to the current context)
+        #[arg(value_name = "REPO")]
+        repos: Vec<String>,
+    },
+
+    /// List tags and the repositories they are attached to
+    List,
+}
@@ -0,0 +1,142 @@
+use std::env;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use git2::Repository;
+
+use crate::{
+    config::{Config, RepoConfig},
+    discover::RepoIdentity,
+    manifest::Manifest,
+    parallel_run::{run_jobs, Job, OutputOptions},
+    summary::RunSummary,
+};
+
+/// Clone the repositories in a manifest that aren't already cloned, then register them all.
+pub fn clone(mut config: Config, manifest_path: &Path, root: Option<&Path>) -> anyhow::Result<()> {
+    let manifest = Manifest::read(manifest_path)?;
+
+    if manifest.repositories.is_empty() {
+        println!("No repositories in '{}'", manifest_path.to_string_lossy());
+        return Ok(());
+    }
+
+    let root = match root {
+        Some(root) => root.to_owned(),
+        None => env::current_dir()?,
+    };
+    create_dir_all(&root)?;
+    let root = root.canonicalize()?;
+
+    let targets: Vec<PathBuf> = manifest
+        .repositories
+        .iter()
+        .map(|repo| root.join(&repo.path))
+        .collect();
+
+    // Clone in batches by depth, so that a repository nested in another is cloned into it once
+    // it exists, rather than getting in the way of its clone
+    let mut depths: Vec<usize> = manifest
+        .repositories
+        .iter()
+        .map(|repo| repo.path.components().count())
+        .collect();
+    depths.sort();
+    depths.dedup();
+
+    let mut summary = RunSummary::default();
+    let mut ran = false;
+
+    for depth in depths {
+        let mut jobs = Vec::new();
+
+        for (repo, target) in manifest.repositories.iter().zip(&targets) {
+            if repo.path.components().count() != depth || target.exists() {
+                continue;
+            }
+            let outer_unfinished = targets.iter().any(|outer| {
+                outer != target
+                    && target.starts_with(outer)
+                    && (!outer.exists() || summary.failed.iter().any(|(path, _)| path == outer))
+            });
+            if outer_unfinished {
+                summary.skipped.push(target.clone());
+                continue;
+            }
+
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+            let mut command = Command::new("git");
+            command.arg("clone");
+            if let Some(branch) = &repo.branch {
+                command.arg("--branch").arg(branch);
+            }
+            command
+                .arg("--")
+                .arg(&repo.url)
+                .arg(target)
+                .current_dir(&root);
+            jobs.push(Job {
+                path: target.clone(),
+                command,
+            });
+        }
+
+        if jobs.is_empty() {
+            continue;
+        }
+
+        let options = OutputOptions {
+            only_errors: true,
+            ..OutputOptions::default()
+        };
+        let batch = run_jobs(&config.settings, jobs, options)?;
+        ran = true;
+        summary.succeeded.extend(batch.succeeded);
+        summary.failed.extend(batch.failed);
+        summary.cancelled.extend(batch.cancelled);
+        if !summary.cancelled.is_empty() {
+            break;
+        }
+    }
+
+    // Register everything that has been cloned, now or before
+    for (repo, target) in manifest.repositories.into_iter().zip(targets) {
+        let unfinished = summary.failed.iter().any(|(path, _)| *path == target)
+            || summary.cancelled.contains(&target);
+        if unfinished || !target.exists() {
+            continue;
+        }
+        if Repository::open(&target).is_err() {
+            eprintln!(
+                "{}: already exists, but is not a git repository",
+                target.to_string_lossy()
+            );
+            continue;
+        }
+
+        let path = target.canonicalize()?;
+        let identity = RepoIdentity::read(&path);
+        let mut repo_config = RepoConfig::new(&path);
+        repo_config.alias = repo.alias;
+        repo_config.tags = repo.tags;
+        repo_config.remote_url = identity.remote_url.or(Some(repo.url));
+        repo_config.root_commit = identity.root_commit;
+
+        if config.add_repo(repo_config) {
+            println!("{}: registered", path.to_string_lossy());
+        } else {
+            println!("{}: already registered", path.to_string_lossy());
+        }
+    }
+
+    config.write()?;
+
+    if ran {
+        summary.finish()
+    } else {
+        Ok(())
+    }
+}
@@ -19,7 +19,7 @@ pub fn completions(shell: CompletionShell, binary_name: &str) -> anyhow::Result<
     }
 
     let mut out = stdout();
-    out.write(&buffer)?;
+    out.write_all(&buffer)?;
     out.flush()?;
     Ok(())
 }
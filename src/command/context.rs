@@ -1,233 +1,222 @@
-use std::io::{self, stdout, Write};
+use std::io::{stdout, Write};
+use std::path::PathBuf;
+
+use anyhow::bail;
 
 use crossterm::{
-    cursor, event,
-    style::{Color, ContentStyle, Print, PrintStyledContent, StyledContent, Stylize},
-    terminal::{self, disable_raw_mode, enable_raw_mode, size},
-    ExecutableCommand, QueueableCommand,
+    style::{Print, PrintStyledContent, Stylize},
+    QueueableCommand,
 };
 
-use crate::{config::Config, path::path_to_string};
+use crate::{
+    command::status::fetch_infos,
+    config::{queue_context_line, Config, RepoConfig},
+    interrupt,
+    path::path_to_string,
+    pattern::{matches_any, parse_patterns, RepoPattern},
+    predicate::Predicate,
+    tui::checklist::{checklist, ChecklistItem},
+};
 
-/// Show the user an interactive checkbox UI for selecting repositories.
-///
-/// The user can move the repository selection using the arrow keys and toggle
-/// the selection using the space bar.
-///
-/// If the number of repositories is greater than the terminal height, the list
-/// is paginated.
+/// Show the user an interactive checkbox UI for selecting the repositories in the context.
 pub fn context_ui(mut config: Config) -> anyhow::Result<()> {
+    interrupt::install();
+
     if config.repositories.is_empty() {
         println!("No repositories registered: use the 'register' command");
         return Ok(());
     }
 
-    let repo_count = config.repositories.len();
-    let (_, height) = size()?;
+    let mut items: Vec<ChecklistItem> = config
+        .repositories
+        .iter()
+        .map(|repo| ChecklistItem {
+            path: repo.path.clone(),
+            checked: repo.visible,
+        })
+        .collect();
 
-    // Not enough space for the UI
-    if height <= 5 {
-        return Ok(());
+    if !checklist(&mut items)? {
+        println!("Interrupted: context unchanged");
+        interrupt::exit();
     }
 
-    // The max page height is the terminal height with a little bit of space for the info
-    // bar, the page indicator, and a blank line at the bottom.
-    let max_page_height = height as usize - 3;
+    for (repo, item) in config.repositories.iter_mut().zip(items) {
+        repo.visible = item.checked;
+    }
 
-    let page_size = repo_count.min(max_page_height);
-    let mut out = stdout();
+    config.write()
+}
 
-    // Long repo names can muck up the redraw
-    out.execute(terminal::DisableLineWrap)?;
+/// Set the context to the repositories with any of the given tags.
+pub fn select_tags(config: Config, tags: &[String]) -> anyhow::Result<()> {
+    select(config, |repo| {
+        tags.iter().any(|tag| repo.tags.contains(tag))
+    })
+}
+
+/// Set the context to the repositories matching any of the patterns.
+pub fn set(config: Config, patterns: &[String]) -> anyhow::Result<()> {
+    let patterns = parse_patterns_warn_unmatched(&config, patterns)?;
+    select(config, |repo| matches_any(&patterns, repo))
+}
 
-    // Perform the first draw of the UI so that when the event_loop moves the cursor up it
-    // moves to the correct place.
-    queue_info_bar(&mut out)?;
-    queue_page_info(&out, &config, 0, page_size)?;
-    queue_repo_list(&out, &config, 0, page_size)?;
+/// Add the repositories matching any of the patterns to the context.
+pub fn add(config: Config, patterns: &[String]) -> anyhow::Result<()> {
+    let patterns = parse_patterns_warn_unmatched(&config, patterns)?;
+    select(config, |repo| repo.visible || matches_any(&patterns, repo))
+}
 
-    event_loop(&mut out, &mut config, page_size)?;
+/// Remove the repositories matching any of the patterns from the context.
+pub fn remove(config: Config, patterns: &[String]) -> anyhow::Result<()> {
+    let patterns = parse_patterns_warn_unmatched(&config, patterns)?;
+    select(config, |repo| repo.visible && !matches_any(&patterns, repo))
+}
 
-    out.execute(terminal::EnableLineWrap)?;
+/// Set the context to the repositories whose git state matches a predicate.
+pub fn select_where(config: Config, predicate: &[String]) -> anyhow::Result<()> {
+    let predicate = Predicate::parse(predicate)?;
+
+    let paths: Vec<PathBuf> = config.repositories.iter().map(|r| r.path.clone()).collect();
+    let matching: Vec<PathBuf> = fetch_infos(&paths)
+        .into_iter()
+        .filter_map(|(path, repo_info)| match repo_info {
+            Ok(info) => predicate.matches(&info).then_some(path),
+            Err(err) => {
+                eprintln!("Error {}: {}", path.to_string_lossy(), err);
+                None
+            }
+        })
+        .collect();
 
-    config.write()
+    select(config, |repo| matching.contains(&repo.path))
 }
 
-fn event_loop(
-    out: &mut io::Stdout,
-    config: &mut Config,
-    page_size: usize,
-) -> Result<(), anyhow::Error> {
-    let repo_count = config.repositories.len();
-    let mut selected = 0;
-    out.execute(cursor::Hide)?;
-    enable_raw_mode()?;
-
-    loop {
-        match event::read()? {
-            event::Event::Key(event) => match event.code {
-                event::KeyCode::Char('+') => config
-                    .repositories
-                    .iter_mut()
-                    .for_each(|r| r.visible = true),
-                event::KeyCode::Char('-') => config
-                    .repositories
-                    .iter_mut()
-                    .for_each(|r| r.visible = false),
-                event::KeyCode::Up | event::KeyCode::Char('k') => {
-                    if selected > 0 {
-                        selected -= 1
-                    }
-                }
-                event::KeyCode::Down | event::KeyCode::Char('j') => {
-                    if selected < repo_count - 1 {
-                        selected += 1
-                    }
-                }
-                event::KeyCode::Left | event::KeyCode::Char('h') => {
-                    if selected >= page_size {
-                        selected -= page_size;
-                    } else {
-                        selected = 0;
-                    }
-                }
-                event::KeyCode::Right | event::KeyCode::Char('l') => {
-                    selected = (selected + page_size).min(repo_count - 1);
-                }
-                event::KeyCode::Enter => break,
-                event::KeyCode::Char(' ') => {
-                    config.repositories[selected].visible = !config.repositories[selected].visible
-                }
-                event::KeyCode::Char('p') => {
-                    if let Some(root) = config.repositories[selected].path.parent() {
-                        let root = root.to_path_buf();
-
-                        let all_selected = config
-                            .repositories
-                            .iter()
-                            .filter(|r| r.path.starts_with(&root))
-                            .all(|r| r.visible);
-
-                        config
-                            .repositories
-                            .iter_mut()
-                            .filter(|r| r.path.starts_with(&root))
-                            .for_each(|r| r.visible = !all_selected);
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
+/// Set the context to the repositories for which `selected` returns true, reporting each
+/// repository that was added or removed.
+pub fn select(mut config: Config, selected: impl Fn(&RepoConfig) -> bool) -> anyhow::Result<()> {
+    let selection: Vec<bool> = config.repositories.iter().map(selected).collect();
+
+    let mut out = stdout();
+    let mut changed = false;
+    for (repo, visible) in config.repositories.iter_mut().zip(selection) {
+        if repo.visible != visible {
+            let line = format!(
+                "{} {}",
+                if visible { "+" } else { "-" },
+                path_to_string(&repo.path)
+            );
+            out.queue(PrintStyledContent(if visible {
+                line.dark_green()
+            } else {
+                line.dark_red()
+            }))?
+            .queue(Print("\r\n"))?;
+            repo.visible = visible;
+            changed = true;
         }
-        out.queue(cursor::MoveUp(page_size as u16 + 1))?;
-        queue_page_info(&*out, config, selected, page_size)?;
-        queue_repo_list(&*out, config, selected, page_size)?;
-        out.flush()?;
     }
 
-    disable_raw_mode()?;
-    out.execute(cursor::Show)?;
-    Ok(())
+    if !changed {
+        out.queue(Print("context unchanged\r\n"))?;
+    }
+    queue_context_line(&out, &config)?;
+    out.flush()?;
+
+    config.write()
 }
 
-fn queue_page_info(
-    mut out: impl QueueableCommand,
+fn parse_patterns_warn_unmatched(
     config: &Config,
-    selected: usize,
-    page_size: usize,
-) -> io::Result<()> {
-    let repo_count = config.repositories.len();
-    let page_count = ((repo_count - 1) / page_size) + 1;
-    let selected_count = config
-        .repositories
-        .iter()
-        .filter(|repo| repo.visible)
-        .count();
-    let selected_page = selected / page_size;
-
-    if page_count > 1 {
-        for i in 0..page_count {
-            out.queue(Print(if i == selected_page { "⦿" } else { "○" }))?;
+    patterns: &[String],
+) -> anyhow::Result<Vec<RepoPattern>> {
+    let parsed = parse_patterns(patterns)?;
+    for (pattern, text) in parsed.iter().zip(patterns) {
+        if !config.repositories.iter().any(|r| pattern.matches(r)) {
+            eprintln!("no registered repository matches '{}'", text);
         }
-        out.queue(Print(format!(
-            " [Page {}/{}] ",
-            selected_page + 1,
-            page_count
-        )))?;
     }
+    Ok(parsed)
+}
 
-    out.queue(Print(format!(
-        "[Selected {}/{}]\r\n",
-        selected_count, repo_count
-    )))?;
-    Ok(())
+/// Save the current context under a name, replacing any context with the same name.
+pub fn save(mut config: Config, name: &str) -> anyhow::Result<()> {
+    if name.is_empty() {
+        bail!("context names cannot be empty");
+    }
+
+    let replaced = config.contexts.contains_key(name);
+    config.save_context(name);
+    let count = config.contexts[name].len();
+    if replaced {
+        println!("{}: updated ({} repositories)", name, count);
+    } else {
+        println!("{}: saved ({} repositories)", name, count);
+    }
+
+    config.write()
 }
 
-fn queue_info_bar(mut out: impl QueueableCommand) -> Result<(), anyhow::Error> {
-    const KEYS: [(&str, &str); 6] = [
-        ("up/down", "move"),
-        ("enter", "confirm"),
-        ("space", "toggle"),
-        ("+", "all"),
-        ("-", "none"),
-        ("p", "toggle path"),
-    ];
-
-    let styled_keys: Vec<String> = KEYS
+/// Switch to a saved context.
+pub fn use_saved(mut config: Config, name: &str) -> anyhow::Result<()> {
+    if !config.use_context(name) {
+        bail!("no saved context named '{}'", name);
+    }
+
+    let missing = config.contexts[name]
         .iter()
-        .map(|(key, description)| format!("{} {}", key.dark_yellow(), description.dark_grey()))
-        .collect();
+        .filter(|path| !config.repositories.iter().any(|r| &r.path == *path))
+        .count();
+    if missing > 0 {
+        eprintln!(
+            "{}: {} saved repositories are no longer registered",
+            name, missing
+        );
+    }
 
-    out.queue(Print(styled_keys.join(&format!("{}", ", ".dark_grey()))))?
-        .queue(Print("\r\n"))?;
+    queue_context_line(stdout(), &config)?;
+    stdout().flush()?;
 
-    Ok(())
+    config.write()
 }
 
-fn queue_repo_list(
-    mut out: impl QueueableCommand,
-    config: &Config,
-    selected: usize,
-    page_size: usize,
-) -> io::Result<()> {
-    let repo_count = config.repositories.len();
-    let page = selected / page_size;
-    let page_start = page * page_size;
-    let page_end = (page_start + page_size).min(repo_count);
-    let repos = &config.repositories[page_start..page_end];
-    for (i, repo) in repos.iter().enumerate() {
-        // Construct the checkbox with the repo name
-        let display = format!(
-            "[{}] {}",
-            if repo.visible { "✓" } else { " " },
-            path_to_string(&repo.path)
+pub fn list(config: Config) -> anyhow::Result<()> {
+    if config.contexts.is_empty() {
+        println!("No saved contexts: use 'context save' to save the current context");
+        return Ok(());
+    }
+
+    for (name, paths) in &config.contexts {
+        let active = config.active_context.as_deref() == Some(name.as_str());
+        let line = format!(
+            "{} {} ({} repositories){}",
+            if active { "*" } else { " " },
+            name,
+            paths.len(),
+            if active && config.active_context_modified() {
+                " (modified)"
+            } else {
+                ""
+            }
         );
-        let mut style = ContentStyle::new();
-        style.foreground_color = if repo.visible {
-            Some(Color::White)
-        } else {
-            Some(Color::Grey)
-        };
-        style.background_color = if i + page_start == selected {
-            Some(Color::DarkBlue)
+        if active {
+            println!("{}", line.bold());
         } else {
-            None
-        };
+            println!("{}", line);
+        }
+    }
 
-        // 'Clear line' is not supported by crossterm, so use the ANSI code.
-        // We are potentially overwriting a page, so if it had a longer repo name we need to clear it.
-        const CLEAR_LINE: &str = "\x1B[2K";
+    Ok(())
+}
 
-        out.queue(Print(CLEAR_LINE))?
-            .queue(PrintStyledContent(StyledContent::new(style, display)))?
-            .queue(Print("\n\r"))?;
+pub fn delete(mut config: Config, name: &str) -> anyhow::Result<()> {
+    if config.contexts.remove(name).is_none() {
+        bail!("no saved context named '{}'", name);
     }
-
-    // Add blank lines to fill out page.
-    // This keeps the pages a consistent size, so that when we move the cursor upwards
-    // by the page size, it goes to the top of the list.
-    for _ in 0..(page_size - (page_end - page_start)) {
-        out.queue(Print("\x1B[2K\n\r"))?;
+    if config.active_context.as_deref() == Some(name) {
+        config.active_context = None;
     }
-    Ok(())
+    println!("{}: deleted", name);
+
+    config.write()
 }
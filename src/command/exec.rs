@@ -1,22 +1,59 @@
-use crate::{config::Config, parallel_run::parallel_run, serial_run::serial_run};
+use std::env;
 
-pub fn run(config: Config, parallel: bool, args: &Vec<String>) -> anyhow::Result<()> {
-    if args.is_empty() {
-        eprintln!("at least one command argument is required.");
-        return Ok(());
-    }
+use crate::{
+    config::Config,
+    parallel_run::{parallel_run, OutputOptions},
+    serial_run::{serial_run, SerialOptions},
+    template::CommandTemplate,
+};
+
+pub fn run(
+    config: Config,
+    parallel: bool,
+    label_streams: bool,
+    stream: bool,
+    serial_options: SerialOptions,
+    shell_script: Option<String>,
+    args: &[String],
+) -> anyhow::Result<()> {
+    let template = match shell_script {
+        Some(script) => CommandTemplate::Shell {
+            shell: shell(&config),
+            script,
+        },
+        None if args.is_empty() => {
+            eprintln!("at least one command argument is required.");
+            return Ok(());
+        }
+        None => CommandTemplate::program(&args[0], &args[1..]),
+    };
 
     if config.repositories.is_empty() {
         println!("No repositories registered: use the 'register' command");
         return Ok(());
     }
 
-    let program = &args[0];
-    let args = &args[1..];
-
     if parallel {
-        parallel_run(config, program, args, false)
+        parallel_run(
+            config,
+            &template,
+            OutputOptions {
+                label_streams,
+                stream,
+                ..OutputOptions::default()
+            },
+        )
     } else {
-        serial_run(config, program, args)
+        serial_run(config, &template, serial_options)
     }
 }
+
+/// The shell to run scripts with: the configured shell, or else the user's shell.
+fn shell(config: &Config) -> String {
+    config
+        .settings
+        .shell
+        .clone()
+        .or_else(|| env::var("SHELL").ok().filter(|shell| !shell.is_empty()))
+        .unwrap_or_else(|| "sh".to_owned())
+}
@@ -0,0 +1,119 @@
+use std::env;
+use std::fs::write;
+use std::io::{stdout, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use git2::Repository;
+
+use crate::{
+    cli_options::ManifestFormat,
+    command::status::read_upstream,
+    config::Config,
+    discover::RepoIdentity,
+    manifest::{Manifest, ManifestRepo},
+};
+
+/// Write a manifest of the selected repositories, from which `clone` can set them up elsewhere.
+pub fn export(
+    config: Config,
+    format: ManifestFormat,
+    root: Option<&Path>,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let root = match root {
+        Some(root) => root
+            .canonicalize()
+            .with_context(|| format!("could not find '{}'", root.to_string_lossy()))?,
+        None => env::current_dir()?,
+    };
+
+    let paths = config.visible_repos();
+    let mut repositories = Vec::new();
+
+    for path in paths {
+        let repo_config = config.repositories.iter().find(|r| r.path == path);
+
+        // Submodules are cloned along with their superproject, and worktrees would be second
+        // clones of the same repository
+        if let Some(superproject) = repo_config.and_then(|r| r.superproject.as_ref()) {
+            eprintln!(
+                "{}: is a submodule of '{}', not exported",
+                path.to_string_lossy(),
+                superproject.to_string_lossy()
+            );
+            continue;
+        }
+
+        let repo = match Repository::open(&path) {
+            Ok(repo) => repo,
+            Err(err) => {
+                eprintln!("Error {}: {}", path.to_string_lossy(), err);
+                continue;
+            }
+        };
+        if repo.is_worktree() {
+            eprintln!(
+                "{}: is a linked worktree, not exported",
+                path.to_string_lossy()
+            );
+            continue;
+        }
+
+        // An absolute path would only be right on this machine
+        let Ok(relative_path) = path.strip_prefix(&root) else {
+            eprintln!(
+                "{}: is not in '{}', not exported",
+                path.to_string_lossy(),
+                root.to_string_lossy()
+            );
+            continue;
+        };
+
+        // Prefer the remote of the branch's upstream, which has the branch to check out. A local
+        // branch that hasn't been pushed can't be cloned, so the remote's default is used then.
+        let upstream_remote_info = repo
+            .head()
+            .ok()
+            .and_then(|head| read_upstream(&repo, &head).1);
+        let (url, branch) = match upstream_remote_info {
+            Some(remote) => (Some(remote.url), Some(remote.branch)),
+            None => {
+                let url = RepoIdentity::read_remote_url(&repo)
+                    .or_else(|| repo_config.and_then(|r| r.remote_url.clone()));
+                (url, None)
+            }
+        };
+        let Some(url) = url else {
+            eprintln!(
+                "{}: has no remote to clone from, not exported",
+                path.to_string_lossy()
+            );
+            continue;
+        };
+
+        repositories.push(ManifestRepo {
+            url,
+            path: relative_path.to_owned(),
+            branch,
+            alias: repo_config.and_then(|r| r.alias.clone()),
+            tags: repo_config.map(|r| r.tags.clone()).unwrap_or_default(),
+        });
+    }
+
+    let manifest = Manifest { repositories };
+    let contents = match format {
+        ManifestFormat::Toml => manifest.to_toml(),
+        ManifestFormat::Json => manifest.to_json()?,
+        ManifestFormat::Yaml => manifest.to_yaml()?,
+    };
+
+    match output {
+        Some(output) => write(output, contents)
+            .with_context(|| format!("failed to write '{}'", output.to_string_lossy())),
+        None => {
+            stdout().write_all(contents.as_bytes())?;
+            Ok(())
+        }
+    }
+}
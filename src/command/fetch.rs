@@ -1,4 +1,8 @@
-use crate::{config::Config, parallel_run::parallel_run};
+use crate::{
+    config::Config,
+    parallel_run::{parallel_run, OutputOptions},
+    template::CommandTemplate,
+};
 
 pub fn fetch(config: Config) -> anyhow::Result<()> {
     if config.repositories.is_empty() {
@@ -6,5 +10,12 @@ pub fn fetch(config: Config) -> anyhow::Result<()> {
         return Ok(());
     }
 
-    parallel_run(config, "git", &["fetch".to_string()], true)
+    parallel_run(
+        config,
+        &CommandTemplate::program("git", &["fetch".to_string()]),
+        OutputOptions {
+            only_errors: true,
+            ..OutputOptions::default()
+        },
+    )
 }
@@ -1,10 +1,14 @@
-use crate::{config::Config, serial_run::serial_run};
+use crate::{
+    config::Config,
+    serial_run::{serial_run, SerialOptions},
+    template::CommandTemplate,
+};
 
-pub fn run(config: Config, args: &Vec<String>) -> anyhow::Result<()> {
+pub fn run(config: Config, options: SerialOptions, args: &[String]) -> anyhow::Result<()> {
     if config.repositories.is_empty() {
         println!("No repositories registered: use the 'register' command");
         return Ok(());
     }
 
-    serial_run(config, "git", args)
+    serial_run(config, &CommandTemplate::program("git", args), options)
 }
@@ -1,9 +1,14 @@
+pub mod clone;
 pub mod completions;
 pub mod context;
 pub mod exec;
+pub mod export;
 pub mod fetch;
-pub mod pull;
 pub mod git;
+pub mod prune;
+pub mod pull;
 pub mod register;
+pub mod relocate;
 pub mod status;
+pub mod tag;
 pub mod unregister;
@@ -0,0 +1,50 @@
+use std::path::{Path, PathBuf};
+
+use git2::Repository;
+
+use crate::config::Config;
+
+/// Why a registered repository should be pruned, if it should be.
+pub fn prune_reason(path: &Path) -> Option<&'static str> {
+    if !path.exists() {
+        Some("missing")
+    } else if Repository::open(path).is_err() {
+        Some("not a git repository")
+    } else {
+        None
+    }
+}
+
+/// Unregister repositories that are missing or are no longer git repositories.
+pub fn prune(mut config: Config, dry_run: bool) -> anyhow::Result<()> {
+    let prunable: Vec<(PathBuf, &str)> = config
+        .repositories
+        .iter()
+        .filter(|r| config.filter.matches(r))
+        .filter_map(|r| prune_reason(&r.path).map(|reason| (r.path.clone(), reason)))
+        .collect();
+
+    if prunable.is_empty() {
+        println!("Nothing to prune");
+        return Ok(());
+    }
+
+    for (path, reason) in &prunable {
+        if dry_run {
+            println!(
+                "{}: {}, would be unregistered",
+                path.to_string_lossy(),
+                reason
+            );
+        } else {
+            config.remove_repo(path);
+            println!("{}: {}, unregistered", path.to_string_lossy(), reason);
+        }
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    config.write()
+}
@@ -1,11 +1,35 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use crate::{config::Config, discover::find_git_repos};
+use crate::{
+    command::prune::prune_reason,
+    config::{Config, RepoConfig},
+    discover::{find_git_repos, DiscoveredRepo, RepoIdentity, RepoKind, ScanOptions},
+    interrupt,
+    tui::checklist::{checklist, ChecklistItem},
+};
 
-pub fn register(mut config: Config, root_path: &Path) -> anyhow::Result<()> {
-    let discovered_repo_paths = find_git_repos(root_path);
+/// How `register` decides which of the discovered repositories to add.
+#[derive(PartialEq)]
+pub enum RegisterMode {
+    /// Add them all.
+    All,
+    /// Only print what would be added.
+    DryRun,
+    /// Let the user choose from a checklist.
+    Interactive,
+}
+
+pub fn register(
+    mut config: Config,
+    root_path: &Path,
+    options: ScanOptions,
+    mode: RegisterMode,
+    rescan: bool,
+) -> anyhow::Result<()> {
+    let discovered_repos = find_git_repos(root_path, &options)?;
 
-    if discovered_repo_paths.is_empty() {
+    if discovered_repos.is_empty() {
         println!(
             "No repositories discovered in '{}'",
             root_path.to_string_lossy()
@@ -13,13 +37,177 @@ pub fn register(mut config: Config, root_path: &Path) -> anyhow::Result<()> {
         return Ok(());
     }
 
-    for repo_path in discovered_repo_paths {
-        if config.add_repo(&repo_path) {
-            println!("{}: registered", repo_path.to_string_lossy());
-        } else {
-            println!("{}: already registered", repo_path.to_string_lossy());
+    let (registered, mut new_repos): (Vec<DiscoveredRepo>, Vec<DiscoveredRepo>) = discovered_repos
+        .into_iter()
+        .partition(|repo| config.repositories.iter().any(|r| r.path == repo.path));
+
+    // Reading an identity walks the repository's history, so only do it when it's needed
+    let mut identities: HashMap<PathBuf, RepoIdentity> = HashMap::new();
+
+    // Registered repositories that no longer exist, and where they have moved to
+    let moves = if rescan {
+        identities.extend(
+            new_repos
+                .iter()
+                .map(|repo| (repo.path.clone(), RepoIdentity::read(&repo.path))),
+        );
+        find_moves(&config, &new_repos, &identities)
+    } else {
+        HashMap::new()
+    };
+    new_repos.retain(|repo| !moves.values().any(|path| *path == repo.path));
+
+    if mode == RegisterMode::Interactive {
+        if new_repos.is_empty() {
+            println!(
+                "All repositories in '{}' are already registered",
+                root_path.to_string_lossy()
+            );
+            return Ok(());
+        }
+
+        interrupt::install();
+
+        let mut items: Vec<ChecklistItem> = new_repos
+            .iter()
+            .map(|repo| ChecklistItem {
+                path: repo.path.clone(),
+                checked: true,
+            })
+            .collect();
+
+        if !checklist(&mut items)? {
+            println!("Interrupted: nothing registered");
+            interrupt::exit();
+        }
+
+        let mut items = items.into_iter();
+        new_repos.retain(|_| items.next().is_some_and(|item| item.checked));
+    }
+
+    for repo in &config.repositories {
+        if let Some(new_path) = moves.get(&repo.path) {
+            println!(
+                "{}: {} {}",
+                repo.path.to_string_lossy(),
+                if mode == RegisterMode::DryRun {
+                    "would be moved to"
+                } else {
+                    "moved to"
+                },
+                new_path.to_string_lossy()
+            );
+        }
+    }
+    config.move_repos(&moves);
+
+    for repo in &registered {
+        println!("{}: already registered", repo.path.to_string_lossy());
+    }
+
+    for repo in new_repos {
+        let description = match (&repo.superproject, repo.kind) {
+            (Some(superproject), _) => {
+                format!(" (submodule of {})", superproject.to_string_lossy())
+            }
+            (None, RepoKind::Standard) => String::new(),
+            (None, kind) => format!(" ({})", kind.as_str()),
+        };
+        let repo_path = repo.path.to_string_lossy().to_string();
+
+        if mode == RegisterMode::DryRun {
+            println!("{}: would be registered{}", repo_path, description);
+            continue;
+        }
+
+        let mut repo_config = RepoConfig::new(&repo.path);
+        repo_config.kind = repo.kind;
+        repo_config.main_repo = repo.main_repo;
+        repo_config.superproject = repo.superproject;
+        let identity = identities
+            .remove(&repo.path)
+            .unwrap_or_else(|| RepoIdentity::read(&repo.path));
+        repo_config.remote_url = identity.remote_url;
+        repo_config.root_commit = identity.root_commit;
+        config.add_repo(repo_config);
+        println!("{}: registered{}", repo_path, description);
+    }
+
+    if mode == RegisterMode::DryRun {
+        return Ok(());
+    }
+
+    // Record the identity of repositories registered before identities were, so that they can
+    // be found by a later `--rescan` if they move
+    for repo in config.repositories.iter_mut() {
+        let unidentified = repo.remote_url.is_none() && repo.root_commit.is_none();
+        if unidentified && registered.iter().any(|r| r.path == repo.path) {
+            let identity = RepoIdentity::read(&repo.path);
+            repo.remote_url = identity.remote_url;
+            repo.root_commit = identity.root_commit;
         }
     }
 
     config.write()
 }
+
+/// Match registered repositories that no longer exist to discovered repositories with the same
+/// identity. Repositories matching more than one discovered repository, or matching the same
+/// one as another repository, are left alone.
+fn find_moves(
+    config: &Config,
+    new_repos: &[DiscoveredRepo],
+    identities: &HashMap<PathBuf, RepoIdentity>,
+) -> HashMap<PathBuf, PathBuf> {
+    let mut matches: Vec<(&PathBuf, Vec<&PathBuf>)> = Vec::new();
+
+    for repo in &config.repositories {
+        if prune_reason(&repo.path).is_none() {
+            continue;
+        }
+        if repo.remote_url.is_none() && repo.root_commit.is_none() {
+            eprintln!(
+                "{}: no longer exists, but was registered without a remote URL or first commit to recognise it by",
+                repo.path.to_string_lossy()
+            );
+            continue;
+        }
+
+        let candidates: Vec<&PathBuf> = new_repos
+            .iter()
+            .map(|r| &r.path)
+            .filter(|path| identities[*path].matches(repo))
+            .collect();
+
+        matches.push((&repo.path, candidates));
+    }
+
+    let mut moves = HashMap::new();
+    for (old_path, candidates) in &matches {
+        match candidates[..] {
+            [] => {}
+            [path] => {
+                let claims = matches
+                    .iter()
+                    .filter(|(_, candidates)| candidates.contains(&path))
+                    .count();
+                if claims == 1 {
+                    moves.insert((*old_path).clone(), path.clone());
+                } else {
+                    eprintln!(
+                        "{}: matches '{}', as do other repositories, not moved",
+                        old_path.to_string_lossy(),
+                        path.to_string_lossy()
+                    );
+                }
+            }
+            _ => eprintln!(
+                "{}: could be any of {} discovered repositories, not moved",
+                old_path.to_string_lossy(),
+                candidates.len()
+            ),
+        }
+    }
+
+    moves
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+
+use crate::config::Config;
+
+/// Change the paths of all registered repositories under one directory to be under another,
+/// e.g. after moving a directory of repositories.
+pub fn relocate(mut config: Config, old_prefix: &Path, new_prefix: &Path) -> anyhow::Result<()> {
+    let old_prefix = absolute(old_prefix)?;
+    let new_prefix = absolute(new_prefix)?;
+
+    let moves: HashMap<PathBuf, PathBuf> = config
+        .repositories
+        .iter()
+        .filter_map(|repo| {
+            let relative = repo.path.strip_prefix(&old_prefix).ok()?;
+            // Joining an empty path would add a trailing separator
+            let new_path = if relative.as_os_str().is_empty() {
+                new_prefix.clone()
+            } else {
+                new_prefix.join(relative)
+            };
+            Some((repo.path.clone(), new_path))
+        })
+        .collect();
+
+    if moves.is_empty() {
+        println!(
+            "No repositories registered in '{}'",
+            old_prefix.to_string_lossy()
+        );
+        return Ok(());
+    }
+
+    for (from, to) in &moves {
+        if !moves.contains_key(to) && config.repositories.iter().any(|r| &r.path == to) {
+            bail!(
+                "'{}' is already registered: unregister it before relocating '{}'",
+                to.to_string_lossy(),
+                from.to_string_lossy()
+            );
+        }
+    }
+
+    for repo in &config.repositories {
+        let Some(to) = moves.get(&repo.path) else {
+            continue;
+        };
+        let from = &repo.path;
+        let missing = if to.exists() { "" } else { " (missing)" };
+        println!(
+            "{} -> {}{}",
+            from.to_string_lossy(),
+            to.to_string_lossy(),
+            missing
+        );
+    }
+
+    config.move_repos(&moves);
+
+    config.write()
+}
+
+/// Make a path absolute, canonicalizing it if it exists. A path that doesn't exist, such as the
+/// old location of moved repositories, is taken as it is, without any trailing separator.
+fn absolute(path: &Path) -> anyhow::Result<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Ok(path);
+    }
+    Ok(env::current_dir()?.join(path).components().collect())
+}
@@ -4,51 +4,95 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::thread::{self, JoinHandle};
 
-use crossterm::style::Stylize;
+use crossterm::style::{Color, Stylize};
 use crossterm::terminal::size;
-use git2::{Repository, StatusOptions};
+use git2::{Reference, Repository, Status, StatusOptions};
 use serde::{Deserialize, Serialize};
 
+use crate::cli_options::OutputFormat;
+use crate::command::prune::prune_reason;
 use crate::config::queue_context_line;
 use crate::config::Config;
+use crate::discover::RepoKind;
 use crate::path::path_to_string;
 use crate::tui::table::queue_table;
 use crate::tui::table::Cell;
 use crate::tui::table::Table;
 
-pub fn status(config: Config) -> anyhow::Result<()> {
+pub fn status(config: Config, format: OutputFormat) -> anyhow::Result<()> {
     if config.repositories.is_empty() {
-        println!("No repositories registered: use the 'register' command");
+        if format == OutputFormat::Table {
+            println!("No repositories registered: use the 'register' command");
+        } else {
+            write_json(Vec::new(), format)?;
+        }
         return Ok(());
     }
 
     let paths = config.visible_repos();
 
-    // Fetch repository info in parallel
-    let info_threads: Vec<JoinHandle<(PathBuf, Result<RepoInfo, git2::Error>)>> = paths
-        .iter()
-        .map(|path| {
-            let thread_path = path.clone();
-            thread::spawn(move || (thread_path.clone(), fetch_info(&thread_path)))
+    let results: Vec<(PathBuf, Result<RepoInfo, git2::Error>)> = fetch_infos(&paths)
+        .into_iter()
+        .map(|(path, repo_info)| {
+            let repo_config = config.repositories.iter().find(|r| r.path == path);
+            let tags = repo_config.map(|r| r.tags.clone()).unwrap_or_default();
+            let superproject = repo_config
+                .and_then(|r| r.superproject.as_deref())
+                .map(path_to_string);
+            let repo_info = repo_info.map(|info| RepoInfo {
+                tags,
+                superproject,
+                ..info
+            });
+            (path, repo_info)
         })
         .collect();
 
-    // Collect threads, printing any errors to stderr
-    let info_repos: Vec<RepoInfo> = info_threads
+    if format != OutputFormat::Table {
+        let entries = results
+            .into_iter()
+            .map(|(path, repo_info)| match repo_info {
+                Ok(info) => StatusEntry::Repo(Box::new(info)),
+                Err(err) => StatusEntry::Error {
+                    path: path.to_string_lossy().to_string(),
+                    error: err.message().to_string(),
+                },
+            })
+            .collect();
+        return write_json(entries, format);
+    }
+
+    // Collect results, printing any errors to stderr
+    let mut prunable = 0;
+    let info_repos: Vec<RepoInfo> = results
         .into_iter()
-        .filter_map(|handle| {
-            let (path, repo_info) = handle.join().unwrap();
-            match repo_info {
-                Ok(info) => Some(info),
-                Err(err) => {
-                    let name = path.to_string_lossy();
-                    eprintln!("Error {}: {}", name, err);
-                    None
+        .filter_map(|(path, repo_info)| match repo_info {
+            Ok(info) => Some(info),
+            Err(err) => {
+                let name = path.to_string_lossy();
+                match prune_reason(&path) {
+                    Some(reason) => {
+                        eprintln!("Error {}: {}", name, reason);
+                        prunable += 1;
+                    }
+                    None => eprintln!("Error {}: {}", name, err),
                 }
+                None
             }
         })
         .collect();
 
+    if prunable > 0 {
+        eprintln!(
+            "{}",
+            format!(
+                "hint: use 'gitlasso prune' to unregister {} repositories that no longer exist",
+                prunable
+            )
+            .dark_grey()
+        );
+    }
+
     // Display the status table
     let (width, _) = size()?;
     queue_context_line(stdout(), &config)?;
@@ -57,11 +101,40 @@ pub fn status(config: Config) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Write status entries as either a single JSON array or as one JSON object per line.
+fn write_json(entries: Vec<StatusEntry>, format: OutputFormat) -> anyhow::Result<()> {
+    let mut out = stdout().lock();
+    if format == OutputFormat::Ndjson {
+        for entry in entries {
+            serde_json::to_writer(&mut out, &entry)?;
+            writeln!(out)?;
+        }
+    } else {
+        serde_json::to_writer_pretty(&mut out, &entries)?;
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
 fn build_table(repos: Vec<RepoInfo>, width: usize) -> Table {
     let mut rows: Vec<Vec<Cell>> = Vec::new();
 
+    // Only show the tags column if there are tags to show
+    let show_tags = repos.iter().any(|repo| !repo.tags.is_empty());
+
+    // Likewise, only show the kind column if there are unusual repositories
+    let show_kind = repos.iter().any(|repo| repo.kind != RepoKind::Standard);
+
     // Header
-    let headers = ["path", "name", "branch", "status", "upstream", "", "commit"];
+    let mut headers = vec!["path", "name"];
+    if show_tags {
+        headers.push("tags");
+    }
+    if show_kind {
+        headers.push("kind");
+    }
+    headers.extend(["branch", "status", "upstream", "", "commit"]);
     rows.push(
         headers
             .into_iter()
@@ -70,15 +143,27 @@ fn build_table(repos: Vec<RepoInfo>, width: usize) -> Table {
     );
 
     // Body
-    for repo in repos {
-        let row: Vec<Cell> = vec![
+    for (depth, repo) in tree_order(repos) {
+        let mut name = vec![repo.name.bold()];
+        if depth > 0 {
+            name.insert(0, format!("{}└ ", "  ".repeat(depth - 1)).dark_grey());
+        }
+        let mut row: Vec<Cell> = vec![
             Cell::plain(repo.parent_path.unwrap_or("-".to_owned())),
-            Cell::new([repo.name.bold()]),
+            Cell::new(name),
+        ];
+        if show_tags {
+            row.push(Cell::new([repo.tags.join(",").dark_cyan()]));
+        }
+        if show_kind {
+            row.push(match repo.kind {
+                RepoKind::Standard => Cell::plain(""),
+                kind => Cell::new([kind.as_str().to_owned().dark_yellow()]),
+            });
+        }
+        row.extend([
             Cell::plain(repo.branch_name),
-            Cell::new([match repo.status {
-                RepoStatus::Clean => "clean".to_string().stylize(),
-                RepoStatus::Modified(n) => format!("{n} modified").red(),
-            }]),
+            status_cell(&repo.status),
             repo.upstream_remote_info
                 .map(|remote_info| {
                     Cell::plain(format!("{}@{}", remote_info.url, remote_info.branch))
@@ -109,40 +194,191 @@ fn build_table(repos: Vec<RepoInfo>, width: usize) -> Table {
                 &repo.latest_commit_hash.chars().take(7).collect::<String>(),
                 repo.latest_commit_message
             )),
-        ];
+        ]);
         rows.push(row);
     }
 
     Table { width, rows }
 }
 
-#[derive(Serialize, Deserialize)]
-struct RepoInfo {
-    parent_path: Option<String>,
-    name: String,
-    branch_name: String,
-    status: RepoStatus,
-    upstream: Option<String>,
-    upstream_remote_info: Option<RemoteInfo>,
-    ahead_behind: Option<(usize, usize)>,
-    latest_commit_hash: String,
-    latest_commit_message: String,
+/// Show the status as compact indicators, e.g. `+1 ~2 ?3` for one staged file, two files with
+/// unstaged changes and three untracked files.
+fn status_cell(status: &RepoStatus) -> Cell {
+    if status.is_clean() && status.untracked == 0 {
+        return Cell::plain("clean");
+    }
+
+    let indicators = [
+        (status.conflicted, "!", Color::Magenta),
+        (status.staged, "+", Color::Green),
+        (status.unstaged, "~", Color::Red),
+        (status.untracked, "?", Color::DarkGrey),
+    ];
+    let mut spans = Vec::new();
+    for (count, symbol, color) in indicators {
+        if count == 0 {
+            continue;
+        }
+        if !spans.is_empty() {
+            spans.push(" ".to_owned().stylize());
+        }
+        spans.push(format!("{}{}", symbol, count).with(color));
+    }
+    Cell::new(spans)
+}
+
+/// Order repositories so that submodules follow their superproject, with how many superprojects
+/// each is nested in. Submodules whose superproject isn't shown are treated as top level.
+fn tree_order(repos: Vec<RepoInfo>) -> Vec<(usize, RepoInfo)> {
+    fn add_children(
+        parent: &str,
+        depth: usize,
+        remaining: &mut Vec<RepoInfo>,
+        ordered: &mut Vec<(usize, RepoInfo)>,
+    ) {
+        while let Some(i) = remaining
+            .iter()
+            .position(|r| r.superproject.as_deref() == Some(parent))
+        {
+            let child = remaining.remove(i);
+            let path = child.path.clone();
+            ordered.push((depth, child));
+            add_children(&path, depth + 1, remaining, ordered);
+        }
+    }
+
+    let paths: Vec<String> = repos.iter().map(|r| r.path.clone()).collect();
+    let (top_level, mut remaining): (Vec<RepoInfo>, Vec<RepoInfo>) =
+        repos.into_iter().partition(|r| {
+            r.superproject
+                .as_ref()
+                .is_none_or(|superproject| !paths.contains(superproject))
+        });
+
+    let mut ordered = Vec::new();
+    for repo in top_level {
+        let path = repo.path.clone();
+        ordered.push((0, repo));
+        add_children(&path, 1, &mut remaining, &mut ordered);
+    }
+    // Only possible if the configuration has a cycle of superprojects
+    ordered.extend(remaining.into_iter().map(|repo| (0, repo)));
+    ordered
+}
+
+/// A single entry in the machine-readable status output. Repositories that could not be read
+/// are reported in place with their error.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum StatusEntry {
+    Repo(Box<RepoInfo>),
+    Error { path: String, error: String },
 }
 
 #[derive(Serialize, Deserialize)]
-enum RepoStatus {
-    Clean,
-    Modified(usize),
+pub struct RepoInfo {
+    pub path: String,
+    pub parent_path: Option<String>,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub kind: RepoKind,
+    /// For a submodule, the path of its superproject.
+    pub superproject: Option<String>,
+    pub branch_name: String,
+    pub status: RepoStatus,
+    pub upstream: Option<String>,
+    pub upstream_remote_info: Option<RemoteInfo>,
+    pub ahead_behind: Option<(usize, usize)>,
+    pub latest_commit_hash: String,
+    pub latest_commit_message: String,
+}
+
+/// The number of files in each state. A file with both staged and unstaged changes is counted
+/// in both.
+#[derive(Serialize, Deserialize, Default)]
+pub struct RepoStatus {
+    pub staged: usize,
+    pub unstaged: usize,
+    pub untracked: usize,
+    pub conflicted: usize,
+}
+
+impl RepoStatus {
+    /// True if no tracked files have been changed. Untracked files don't count.
+    pub fn is_clean(&self) -> bool {
+        self.staged == 0 && self.unstaged == 0 && self.conflicted == 0
+    }
+
+    fn read(repo: &Repository) -> Result<Self, git2::Error> {
+        let mut status = RepoStatus::default();
+
+        // Bare repositories have no working tree to be modified
+        if repo.is_bare() {
+            return Ok(status);
+        }
+
+        let mut status_options = StatusOptions::new();
+        status_options.include_ignored(false);
+        status_options.include_untracked(true);
+        status_options.recurse_untracked_dirs(false);
+
+        let staged = Status::INDEX_NEW
+            | Status::INDEX_MODIFIED
+            | Status::INDEX_DELETED
+            | Status::INDEX_RENAMED
+            | Status::INDEX_TYPECHANGE;
+        let unstaged =
+            Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE;
+
+        for entry in repo.statuses(Some(&mut status_options))?.iter() {
+            let flags = entry.status();
+            if flags.is_conflicted() {
+                status.conflicted += 1;
+                continue;
+            }
+            if flags.intersects(staged) {
+                status.staged += 1;
+            }
+            if flags.intersects(unstaged) {
+                status.unstaged += 1;
+            }
+            if flags.is_wt_new() {
+                status.untracked += 1;
+            }
+        }
+
+        Ok(status)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-struct RemoteInfo {
-    url: String,
-    branch: String,
+pub struct RemoteInfo {
+    pub url: String,
+    pub branch: String,
+}
+
+/// Fetch info on several git repositories in parallel, returning the results in the same order
+/// as the paths.
+pub fn fetch_infos(paths: &[PathBuf]) -> Vec<(PathBuf, Result<RepoInfo, git2::Error>)> {
+    let info_threads: Vec<JoinHandle<(PathBuf, Result<RepoInfo, git2::Error>)>> = paths
+        .iter()
+        .map(|path| {
+            let thread_path = path.clone();
+            thread::spawn(move || {
+                let info = fetch_info(&thread_path);
+                (thread_path, info)
+            })
+        })
+        .collect();
+
+    info_threads
+        .into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect()
 }
 
 /// Fetch info on a git repository
-fn fetch_info(repo_path: &Path) -> Result<RepoInfo, git2::Error> {
+pub fn fetch_info(repo_path: &Path) -> Result<RepoInfo, git2::Error> {
     let repo = Repository::open(repo_path)?;
 
     let name = repo_path
@@ -150,17 +386,27 @@ fn fetch_info(repo_path: &Path) -> Result<RepoInfo, git2::Error> {
         .map(|name| name.to_string_lossy().to_string())
         .unwrap_or("-".to_owned());
 
+    let path = repo_path.to_string_lossy().to_string();
+
     let parent_path = repo_path.parent().map(path_to_string);
 
+    let kind = RepoKind::of(&repo, repo_path);
+
+    let status = RepoStatus::read(&repo)?;
+
     let head = match repo.head() {
         Ok(head) => head,
         Err(_) => {
             // No 'head' means this is probably an empty repository
             return Ok(RepoInfo {
+                path,
                 name,
+                tags: Vec::new(),
+                kind,
+                superproject: None,
                 parent_path,
                 branch_name: "-".to_string(),
-                status: RepoStatus::Clean,
+                status,
                 upstream: None,
                 upstream_remote_info: None,
                 ahead_behind: None,
@@ -170,34 +416,13 @@ fn fetch_info(repo_path: &Path) -> Result<RepoInfo, git2::Error> {
         }
     };
 
-    let mut status_options = StatusOptions::new();
-    status_options.include_ignored(false);
-    status_options.include_untracked(false);
-
-    let modified_count = repo
-        .statuses(Some(&mut status_options))?
-        .iter()
-        .filter(|status| status.status() != git2::Status::CURRENT)
-        .count();
-
-    let status = if modified_count > 0 {
-        RepoStatus::Modified(modified_count)
-    } else {
-        RepoStatus::Clean
-    };
-
-    let branch_ref_name = head.name().unwrap_or("?").to_string();
-
     let branch_shorthand = head.shorthand().unwrap_or("?").to_string();
 
-    let upstream_ref_name = repo
-        .branch_upstream_name(&branch_ref_name)
-        .ok()
-        .and_then(|name| name.as_str().map(str::to_string));
+    let (upstream_ref_name, upstream_remote_info) = read_upstream(&repo, &head);
 
     let upstream_reference = upstream_ref_name
         .as_ref()
-        .and_then(|name| repo.find_reference(&name).ok());
+        .and_then(|name| repo.find_reference(name).ok());
 
     let ahead_behind = match (head.target(), upstream_reference.and_then(|r| r.target())) {
         (Some(head_oid), Some(upstream_oid)) => {
@@ -206,6 +431,34 @@ fn fetch_info(repo_path: &Path) -> Result<RepoInfo, git2::Error> {
         _ => None,
     };
 
+    let head_commit = head.peel_to_commit()?;
+
+    Ok(RepoInfo {
+        path,
+        name,
+        tags: Vec::new(),
+        kind,
+        superproject: None,
+        parent_path,
+        branch_name: branch_shorthand,
+        status,
+        upstream: upstream_ref_name,
+        upstream_remote_info,
+        ahead_behind,
+        latest_commit_hash: head_commit.id().to_string(),
+        latest_commit_message: head_commit.summary().unwrap_or("-").to_string(),
+    })
+}
+
+/// Read the upstream of the checked out branch: the name of its reference, and the URL of its
+/// remote with the name of the branch on that remote.
+pub fn read_upstream(repo: &Repository, head: &Reference) -> (Option<String>, Option<RemoteInfo>) {
+    let upstream_ref_name = head.name().and_then(|name| {
+        repo.branch_upstream_name(name)
+            .ok()
+            .and_then(|name| name.as_str().map(str::to_string))
+    });
+
     let upstream_remote_info = upstream_ref_name
         .as_ref()
         .and_then(|name| name.strip_prefix("refs/remotes/"))
@@ -215,22 +468,10 @@ fn fetch_info(repo_path: &Path) -> Result<RepoInfo, git2::Error> {
                 .ok()
                 .and_then(|remote| remote.url().map(|url| url.to_string()))
                 .map(|url| RemoteInfo {
-                    url: url,
+                    url,
                     branch: branch.to_string(),
                 })
         });
 
-    let head_commit = head.peel_to_commit()?;
-
-    Ok(RepoInfo {
-        name,
-        parent_path,
-        branch_name: branch_shorthand,
-        status,
-        upstream: upstream_ref_name,
-        upstream_remote_info,
-        ahead_behind: ahead_behind,
-        latest_commit_hash: head_commit.id().to_string(),
-        latest_commit_message: head_commit.summary().unwrap_or("-").to_string(),
-    })
+    (upstream_ref_name, upstream_remote_info)
 }
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+use crossterm::style::Stylize;
+
+use crate::{config::Config, path::path_to_string, pattern::RepoPattern};
+
+pub fn add(mut config: Config, tag: &str, repos: &[String]) -> anyhow::Result<()> {
+    validate_tag(tag)?;
+
+    for i in resolve_repos(&config, repos)? {
+        let repo = &mut config.repositories[i];
+        if repo.tags.iter().any(|t| t == tag) {
+            println!("{}: already tagged '{}'", path_to_string(&repo.path), tag);
+        } else {
+            repo.tags.push(tag.to_owned());
+            println!("{}: tagged '{}'", path_to_string(&repo.path), tag);
+        }
+    }
+
+    config.write()
+}
+
+pub fn remove(mut config: Config, tag: &str, repos: &[String]) -> anyhow::Result<()> {
+    for i in resolve_repos(&config, repos)? {
+        let repo = &mut config.repositories[i];
+        if repo.tags.iter().any(|t| t == tag) {
+            repo.tags.retain(|t| t != tag);
+            println!("{}: untagged '{}'", path_to_string(&repo.path), tag);
+        } else {
+            println!("{}: not tagged '{}'", path_to_string(&repo.path), tag);
+        }
+    }
+
+    config.write()
+}
+
+pub fn list(config: Config) -> anyhow::Result<()> {
+    let mut tags: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for repo in &config.repositories {
+        for tag in &repo.tags {
+            tags.entry(tag)
+                .or_default()
+                .push(path_to_string(&repo.path));
+        }
+    }
+
+    if tags.is_empty() {
+        println!("No tags: use 'tag add' to tag repositories");
+        return Ok(());
+    }
+
+    for (tag, paths) in tags {
+        println!("{} ({})", tag.bold(), paths.len());
+        for path in paths {
+            println!("  {}", path);
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_tag(tag: &str) -> anyhow::Result<()> {
+    if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+        bail!("tags must be non-empty and cannot contain whitespace or commas");
+    }
+    Ok(())
+}
+
+/// Resolve repository patterns to indices in the configuration. With no patterns, all
+/// repositories in the current context are used.
+fn resolve_repos(config: &Config, repos: &[String]) -> anyhow::Result<Vec<usize>> {
+    if repos.is_empty() {
+        let visible = config.visible_repos();
+        return Ok(config
+            .repositories
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| visible.contains(&r.path))
+            .map(|(i, _)| i)
+            .collect());
+    }
+
+    let mut indices = Vec::new();
+    for repo in repos {
+        let pattern = RepoPattern::parse(repo)?;
+        let mut found = false;
+        for (i, _) in config
+            .repositories
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| pattern.matches(r))
+        {
+            found = true;
+            if !indices.contains(&i) {
+                indices.push(i);
+            }
+        }
+        if !found {
+            bail!("no registered repository matches '{}'", repo);
+        }
+    }
+    Ok(indices)
+}
@@ -1,85 +1,480 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use crossterm::style::{Print, PrintStyledContent, Stylize};
 use crossterm::QueueableCommand;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{create_dir_all, read_to_string, File};
 use std::io::Result;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml_edit::{table, value, Array, ArrayOfTables, DocumentMut, Item, Table, Value};
+
+use crate::discover::RepoKind;
+use crate::filter::RepoFilter;
+
+/// The version of the configuration file format written by this version of GitLasso.
+const CONFIG_VERSION: i64 = 1;
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The line-based file used before the TOML configuration was introduced.
+const LEGACY_FILE_NAME: &str = "repositories";
 
 pub struct Config {
     pub path: PathBuf,
     pub repositories: Vec<RepoConfig>,
+    /// Saved contexts, by name, each listing the paths of the repositories it selects.
+    pub contexts: BTreeMap<String, Vec<PathBuf>>,
+    /// The name of the saved context that was last used.
+    pub active_context: Option<String>,
+    /// Settings from the `settings` table. These are only read, never written, so can be
+    /// overridden for a single invocation.
+    pub settings: Settings,
+    /// Restricts the visible repositories for this invocation only. This is never written.
+    pub filter: RepoFilter,
+    /// The parsed configuration file. This is kept so that keys GitLasso doesn't know about,
+    /// comments and ordering are preserved when the configuration is written back.
+    document: DocumentMut,
+}
+
+#[derive(Default)]
+pub struct Settings {
+    /// The maximum number of commands to run at once in parallel. No limit if unset or zero.
+    pub jobs: Option<usize>,
+    /// How long a command may run in each repository before it is killed.
+    pub timeout: Option<Duration>,
+    /// The shell used to run `exec --shell` scripts. Defaults to `$SHELL`.
+    pub shell: Option<String>,
+    /// Patterns for directories `register` never looks in, as in `.gitlassoignore`.
+    pub ignore: Vec<String>,
+}
+
+impl Settings {
+    fn from_table(table: &Table) -> anyhow::Result<Self> {
+        let jobs = match table.get("jobs") {
+            None => None,
+            Some(item) => Some(
+                item.as_integer()
+                    .and_then(|jobs| usize::try_from(jobs).ok())
+                    .with_context(|| "'settings.jobs' should be a non-negative integer")?,
+            ),
+        };
+
+        let timeout = match table.get("timeout") {
+            None => None,
+            Some(item) => Some(
+                item.as_str()
+                    .and_then(|timeout| humantime::parse_duration(timeout).ok())
+                    .with_context(|| "'settings.timeout' should be a duration, e.g. \"30s\"")?,
+            ),
+        };
+
+        let shell = match table.get("shell") {
+            None => None,
+            Some(item) => Some(
+                item.as_str()
+                    .map(str::to_owned)
+                    .with_context(|| "'settings.shell' should be a string")?,
+            ),
+        };
+
+        let ignore = match table.get("ignore") {
+            None => Vec::new(),
+            Some(item) => item
+                .as_array()
+                .and_then(|array| {
+                    array
+                        .iter()
+                        .map(|v| v.as_str().map(str::to_owned))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .with_context(|| "'settings.ignore' should be an array of strings")?,
+        };
+
+        Ok(Settings {
+            jobs,
+            timeout,
+            shell,
+            ignore,
+        })
+    }
 }
 
 pub struct RepoConfig {
     pub path: PathBuf,
     pub visible: bool,
+    pub alias: Option<String>,
+    pub tags: Vec<String>,
+    pub remote_url: Option<String>,
+    /// How the repository is laid out, as found when it was registered.
+    pub kind: RepoKind,
+    /// For a linked worktree, the repository it belongs to.
+    pub main_repo: Option<PathBuf>,
+    /// For a submodule, the repository it is a submodule of.
+    pub superproject: Option<PathBuf>,
+    /// The first commit in the repository's history, used to recognise it if it moves.
+    pub root_commit: Option<String>,
+    pub options: Table,
+    /// The table this repository was read from, including any unknown keys.
+    table: Table,
 }
 
-pub fn read(repositories_path: &Path) -> anyhow::Result<Config> {
-    if !Path::new(repositories_path).exists() {
-        return Ok(Config {
-            path: repositories_path.to_path_buf(),
-            repositories: Vec::new(),
-        });
+impl RepoConfig {
+    pub fn new(path: &Path) -> Self {
+        RepoConfig {
+            path: path.to_owned(),
+            visible: true,
+            alias: None,
+            tags: Vec::new(),
+            remote_url: None,
+            kind: RepoKind::default(),
+            main_repo: None,
+            superproject: None,
+            root_commit: None,
+            options: Table::new(),
+            table: Table::new(),
+        }
+    }
+
+    /// The name of the repository's directory.
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or("-".to_owned())
+    }
+
+    fn from_table(table: &Table) -> anyhow::Result<Self> {
+        let Some(path) = table.get("path").and_then(Item::as_str) else {
+            bail!("repository entry is missing a 'path'");
+        };
+
+        let kind = match table.get("kind") {
+            None => RepoKind::default(),
+            Some(item) => item.as_str().and_then(RepoKind::parse).with_context(|| {
+                format!(
+                    "'{}' has an unknown kind: expected standard, bare, worktree or separate-git-dir",
+                    path
+                )
+            })?,
+        };
+
+        Ok(RepoConfig {
+            path: PathBuf::from(path),
+            visible: table.get("visible").and_then(Item::as_bool).unwrap_or(true),
+            alias: table.get("alias").and_then(Item::as_str).map(str::to_owned),
+            tags: table
+                .get("tags")
+                .and_then(Item::as_array)
+                .map(|tags| {
+                    tags.iter()
+                        .filter_map(|t| t.as_str())
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            remote_url: table
+                .get("remote_url")
+                .and_then(Item::as_str)
+                .map(str::to_owned),
+            kind,
+            main_repo: table
+                .get("main_repo")
+                .and_then(Item::as_str)
+                .map(PathBuf::from),
+            superproject: table
+                .get("superproject")
+                .and_then(Item::as_str)
+                .map(PathBuf::from),
+            root_commit: table
+                .get("root_commit")
+                .and_then(Item::as_str)
+                .map(str::to_owned),
+            options: table
+                .get("options")
+                .and_then(Item::as_table)
+                .cloned()
+                .unwrap_or_default(),
+            table: table.clone(),
+        })
+    }
+
+    fn to_table(&self) -> Table {
+        let mut table = self.table.clone();
+        set_value(
+            &mut table,
+            "path",
+            self.path.to_string_lossy().as_ref().into(),
+        );
+        set_value(&mut table, "visible", self.visible.into());
+        set_optional(&mut table, "alias", self.alias.as_deref().map(Value::from));
+        set_optional(
+            &mut table,
+            "tags",
+            (!self.tags.is_empty()).then(|| Value::Array(self.tags.iter().collect::<Array>())),
+        );
+        set_optional(
+            &mut table,
+            "remote_url",
+            self.remote_url.as_deref().map(Value::from),
+        );
+        set_optional(
+            &mut table,
+            "kind",
+            (self.kind != RepoKind::Standard).then(|| self.kind.as_str().into()),
+        );
+        set_optional(
+            &mut table,
+            "main_repo",
+            self.main_repo
+                .as_ref()
+                .map(|path| path.to_string_lossy().as_ref().into()),
+        );
+        set_optional(
+            &mut table,
+            "superproject",
+            self.superproject
+                .as_ref()
+                .map(|path| path.to_string_lossy().as_ref().into()),
+        );
+        set_optional(
+            &mut table,
+            "root_commit",
+            self.root_commit.as_deref().map(Value::from),
+        );
+        if self.options.is_empty() {
+            table.remove("options");
+        } else {
+            table.insert("options", Item::Table(self.options.clone()));
+        }
+        table
+    }
+}
+
+/// Set a value in a table, keeping the formatting of any existing value.
+fn set_value(table: &mut Table, key: &str, mut new_value: Value) {
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(existing) => {
+            if existing.to_string().trim() != new_value.to_string().trim() {
+                *new_value.decor_mut() = existing.decor().clone();
+                *existing = new_value;
+            }
+        }
+        None => {
+            table.insert(key, value(new_value));
+        }
+    }
+}
+
+fn set_optional(table: &mut Table, key: &str, new_value: Option<Value>) {
+    match new_value {
+        Some(new_value) => set_value(table, key, new_value),
+        None => {
+            table.remove(key);
+        }
+    }
+}
+
+/// Read the configuration from the configuration directory. If there is no configuration file
+/// but there is a repositories file from an older version, it is migrated.
+pub fn read(config_dir: &Path) -> anyhow::Result<Config> {
+    let config_path = config_dir.join(CONFIG_FILE_NAME);
+
+    if !config_path.exists() {
+        let legacy_path = config_dir.join(LEGACY_FILE_NAME);
+        if legacy_path.exists() {
+            let config = read_legacy(&legacy_path, &config_path)?;
+            config.write()?;
+            eprintln!(
+                "Migrated '{}' to '{}'",
+                legacy_path.to_string_lossy(),
+                config_path.to_string_lossy()
+            );
+            return Ok(config);
+        }
+
+        return Config::from_document(config_path, DocumentMut::new());
+    }
+
+    let str =
+        read_to_string(&config_path).with_context(|| "failed to read the configuration file")?;
+
+    let document: DocumentMut = str
+        .parse()
+        .with_context(|| "failed to parse the configuration file")?;
+
+    if let Some(version) = document.get("version").and_then(Item::as_integer) {
+        if version > CONFIG_VERSION {
+            bail!(
+                "the configuration file is version {}, but this version of GitLasso only supports up to version {}",
+                version,
+                CONFIG_VERSION
+            );
+        }
     }
 
-    let str = read_to_string(repositories_path)
-        .with_context(|| "failed to read the repositories file")?;
+    Config::from_document(config_path, document)
+}
+
+/// Read the repositories file used by older versions: one path per line, with a leading '#'
+/// marking a repository that is not in the current context.
+fn read_legacy(legacy_path: &Path, config_path: &Path) -> anyhow::Result<Config> {
+    let str =
+        read_to_string(legacy_path).with_context(|| "failed to read the repositories file")?;
 
     let repositories = str
         .lines()
+        .filter(|line| !line.is_empty())
         .map(|line| match line.strip_prefix("#") {
             Some(str_path) => RepoConfig {
-                path: PathBuf::from(str_path),
                 visible: false,
+                ..RepoConfig::new(Path::new(str_path))
             },
-            None => RepoConfig {
-                path: PathBuf::from(line),
-                visible: true,
-            },
+            None => RepoConfig::new(Path::new(line)),
         })
         .collect();
 
     Ok(Config {
-        path: repositories_path.to_path_buf(),
         repositories,
+        ..Config::from_document(config_path.to_path_buf(), DocumentMut::new())?
     })
 }
 
 impl Config {
+    fn from_document(path: PathBuf, document: DocumentMut) -> anyhow::Result<Config> {
+        let repositories = match document.get("repositories") {
+            None => Vec::new(),
+            Some(item) => item
+                .as_array_of_tables()
+                .with_context(|| "'repositories' should be an array of tables")?
+                .iter()
+                .map(RepoConfig::from_table)
+                .collect::<anyhow::Result<Vec<RepoConfig>>>()
+                .with_context(|| "failed to parse the configuration file")?,
+        };
+
+        let mut contexts = BTreeMap::new();
+        if let Some(item) = document.get("contexts") {
+            let table = item
+                .as_table_like()
+                .with_context(|| "'contexts' should be a table")?;
+            for (name, context) in table.iter() {
+                let paths = context
+                    .get("repositories")
+                    .and_then(Item::as_array)
+                    .map(|paths| {
+                        paths
+                            .iter()
+                            .filter_map(|p| p.as_str())
+                            .map(PathBuf::from)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                contexts.insert(name.to_owned(), paths);
+            }
+        }
+
+        let active_context = document
+            .get("active_context")
+            .and_then(Item::as_str)
+            .map(str::to_owned);
+
+        let settings = match document.get("settings") {
+            None => Settings::default(),
+            Some(item) => Settings::from_table(
+                item.as_table()
+                    .with_context(|| "'settings' should be a table")?,
+            )?,
+        };
+
+        Ok(Config {
+            path,
+            repositories,
+            contexts,
+            active_context,
+            settings,
+            filter: RepoFilter::default(),
+            document,
+        })
+    }
+
     pub fn write(&self) -> anyhow::Result<()> {
-        let repositories_string = self
-            .repositories
-            .iter()
-            .map(|repo| {
-                if repo.visible {
-                    format!("{}", repo.path.to_string_lossy())
-                } else {
-                    format!("#{}", repo.path.to_string_lossy())
-                }
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
+        let mut document = self.document.clone();
+
+        set_value(document.as_table_mut(), "version", CONFIG_VERSION.into());
+
+        let mut repositories = ArrayOfTables::new();
+        for repo in &self.repositories {
+            repositories.push(repo.to_table());
+        }
+        document.insert("repositories", Item::ArrayOfTables(repositories));
+
+        if !self.contexts.is_empty() || document.contains_key("contexts") {
+            let contexts = document["contexts"]
+                .or_insert(table())
+                .as_table_mut()
+                .with_context(|| "'contexts' should be a table")?;
+            contexts.set_implicit(true);
+            contexts.retain(|name, _| self.contexts.contains_key(name));
+            for (name, paths) in &self.contexts {
+                let mut array: Array = paths
+                    .iter()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .collect();
+                // One path per line
+                array
+                    .iter_mut()
+                    .for_each(|p| p.decor_mut().set_prefix("\n    "));
+                array.set_trailing_comma(true);
+                array.set_trailing("\n");
+                contexts[name.as_str()].or_insert(table())["repositories"] = value(array);
+            }
+        }
+
+        match &self.active_context {
+            Some(name) => set_value(document.as_table_mut(), "active_context", name.into()),
+            None => {
+                document.remove("active_context");
+            }
+        }
+
         if let Some(parent) = self.path.parent() {
             create_dir_all(parent)?;
         }
         let mut file =
             File::create(&self.path).with_context(|| "failed to create the configuration file")?;
-        file.write_all(repositories_string.as_bytes())
+        file.write_all(document.to_string().as_bytes())
             .with_context(|| "failed to write the configuration file")
     }
 
-    pub fn add_repo(&mut self, repo_path: &Path) -> bool {
-        if self.repositories.iter().any(|r| r.path == repo_path) {
+    pub fn add_repo(&mut self, repo: RepoConfig) -> bool {
+        if self.repositories.iter().any(|r| r.path == repo.path) {
             return false;
         }
 
-        self.repositories.push(RepoConfig {
-            path: repo_path.to_owned(),
-            visible: true,
+        self.repositories.push(repo);
+        true
+    }
+
+    /// Change the paths of registered repositories, along with any references to them, keeping
+    /// their settings and context membership. `moves` maps old paths to new paths, and all are
+    /// moved at once so that old and new paths can overlap.
+    pub fn move_repos(&mut self, moves: &HashMap<PathBuf, PathBuf>) {
+        let paths = self.repositories.iter_mut().flat_map(|r| {
+            [
+                Some(&mut r.path),
+                r.main_repo.as_mut(),
+                r.superproject.as_mut(),
+            ]
+            .into_iter()
+            .flatten()
         });
-        return true;
+        let context_paths = self.contexts.values_mut().flatten();
+        for path in paths.chain(context_paths) {
+            if let Some(new_path) = moves.get(path) {
+                *path = new_path.clone();
+            }
+        }
     }
 
     pub fn remove_repo(&mut self, repo_path: &Path) -> bool {
@@ -91,7 +486,7 @@ impl Config {
     pub fn visible_repos(&self) -> Vec<PathBuf> {
         self.repositories
             .iter()
-            .filter(|&r| r.visible)
+            .filter(|&r| r.visible && self.filter.matches(r))
             .map(|r| r.path.clone())
             .collect()
     }
@@ -99,21 +494,79 @@ impl Config {
     pub fn invisible_repos(&self) -> Vec<PathBuf> {
         self.repositories
             .iter()
-            .filter(|&r| !r.visible)
+            .filter(|&r| !r.visible && self.filter.matches(r))
             .map(|r| r.path.clone())
             .collect()
     }
+
+    /// Save the repositories in the current context under a name.
+    pub fn save_context(&mut self, name: &str) {
+        let paths = self
+            .repositories
+            .iter()
+            .filter(|r| r.visible)
+            .map(|r| r.path.clone())
+            .collect();
+        self.contexts.insert(name.to_owned(), paths);
+        self.active_context = Some(name.to_owned());
+    }
+
+    /// Make a saved context the current context. Returns false if there is no context with
+    /// that name.
+    pub fn use_context(&mut self, name: &str) -> bool {
+        let Some(paths) = self.contexts.get(name) else {
+            return false;
+        };
+        for repo in self.repositories.iter_mut() {
+            repo.visible = paths.contains(&repo.path);
+        }
+        self.active_context = Some(name.to_owned());
+        true
+    }
+
+    /// True if the current context has changed since the active context was saved or used.
+    pub fn active_context_modified(&self) -> bool {
+        let Some(paths) = self
+            .active_context
+            .as_ref()
+            .and_then(|name| self.contexts.get(name))
+        else {
+            return false;
+        };
+        self.repositories
+            .iter()
+            .any(|r| r.visible != paths.contains(&r.path))
+    }
 }
 
 pub fn queue_context_line(mut f: impl QueueableCommand, config: &Config) -> Result<()> {
     let visible = config.visible_repos().len();
     let total = config.repositories.len();
-    if visible == total {
+    let active_context = config
+        .active_context
+        .as_ref()
+        .filter(|name| config.contexts.contains_key(*name));
+    if visible == total && active_context.is_none() {
         return Ok(());
     }
-    f.queue(PrintStyledContent(
-        format!("context: {} of {} repositories", visible, total).dark_grey(),
-    ))?;
+    let mut line = match active_context {
+        Some(name) => format!(
+            "context: {}{} ({} of {} repositories)",
+            name,
+            if config.active_context_modified() {
+                " (modified)"
+            } else {
+                ""
+            },
+            visible,
+            total
+        ),
+        None => format!("context: {} of {} repositories", visible, total),
+    };
+    if !config.filter.is_empty() {
+        line.push_str(&format!(" ({})", config.filter.describe()));
+    }
+    f.queue(PrintStyledContent(line.dark_grey()))?;
     f.queue(Print("\r\n"))?;
     Ok(())
 }
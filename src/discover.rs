@@ -1,21 +1,338 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
 use std::path::{Path, PathBuf};
 
+use anyhow::Context;
+use git2::{Repository, RepositoryOpenFlags};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
-fn is_git_repo(path: &Path) -> bool {
-    path.join(".git").exists()
+use crate::config::RepoConfig;
+
+/// How a repository's working tree and git directory are laid out.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepoKind {
+    /// A working tree with a `.git` directory, or a submodule, whose git directory is kept in
+    /// its superproject's.
+    #[default]
+    Standard,
+    /// A repository without a working tree.
+    Bare,
+    /// A linked worktree, created with `git worktree add`.
+    Worktree,
+    /// A working tree with a `.git` file pointing to its git directory elsewhere, e.g. created
+    /// with `git clone --separate-git-dir`.
+    SeparateGitDir,
+}
+
+impl RepoKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RepoKind::Standard => "standard",
+            RepoKind::Bare => "bare",
+            RepoKind::Worktree => "worktree",
+            RepoKind::SeparateGitDir => "separate-git-dir",
+        }
+    }
+
+    pub fn parse(str: &str) -> Option<Self> {
+        [
+            RepoKind::Standard,
+            RepoKind::Bare,
+            RepoKind::Worktree,
+            RepoKind::SeparateGitDir,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str() == str)
+    }
+
+    /// Work out the kind of an open repository at `path`.
+    pub fn of(repo: &Repository, path: &Path) -> Self {
+        if repo.is_bare() {
+            RepoKind::Bare
+        } else if repo.is_worktree() {
+            RepoKind::Worktree
+        } else if path.join(".git").is_file() && !is_submodule_git_dir(repo.path()) {
+            RepoKind::SeparateGitDir
+        } else {
+            RepoKind::Standard
+        }
+    }
 }
 
-pub fn find_git_repos(root_dir: &Path) -> Vec<PathBuf> {
+/// Whether a git directory is a submodule's, in the `modules` directory of its superproject's
+/// git directory.
+fn is_submodule_git_dir(git_dir: &Path) -> bool {
+    git_dir.ancestors().any(|dir| {
+        dir.file_name() == Some(OsStr::new("modules"))
+            && dir
+                .parent()
+                .is_some_and(|parent| parent.join("HEAD").is_file())
+    })
+}
+
+/// A repository found by `find_git_repos`.
+pub struct DiscoveredRepo {
+    pub path: PathBuf,
+    pub kind: RepoKind,
+    /// For a linked worktree, the repository it belongs to.
+    pub main_repo: Option<PathBuf>,
+    /// For a submodule, the repository it is a submodule of.
+    pub superproject: Option<PathBuf>,
+}
+
+/// What identifies a repository wherever it is: where it was cloned from and where its history
+/// starts.
+#[derive(Default)]
+pub struct RepoIdentity {
+    /// The URL of the `origin` remote, or of the only remote.
+    pub remote_url: Option<String>,
+    pub root_commit: Option<String>,
+}
+
+impl RepoIdentity {
+    pub fn read(path: &Path) -> Self {
+        let Ok(repo) = Repository::open(path) else {
+            return RepoIdentity::default();
+        };
+
+        let remote_url = Self::read_remote_url(&repo);
+
+        // Follow first parents from HEAD back to the start of history
+        let root_commit = repo.revwalk().ok().and_then(|mut walk| {
+            walk.push_head().ok()?;
+            walk.simplify_first_parent().ok()?;
+            walk.filter_map(Result::ok)
+                .last()
+                .map(|oid| oid.to_string())
+        });
+
+        RepoIdentity {
+            remote_url,
+            root_commit,
+        }
+    }
+
+    /// The URL of the `origin` remote, or of the only remote.
+    pub fn read_remote_url(repo: &Repository) -> Option<String> {
+        repo.find_remote("origin")
+            .ok()
+            .or_else(|| {
+                let remotes = repo.remotes().ok()?;
+                match remotes.len() {
+                    1 => repo.find_remote(remotes.get(0)?).ok(),
+                    _ => None,
+                }
+            })
+            .and_then(|remote| remote.url().map(str::to_owned))
+    }
+
+    /// Whether this is the identity recorded for a registered repository. Everything recorded
+    /// must match.
+    pub fn matches(&self, repo: &RepoConfig) -> bool {
+        let recorded = [
+            (&repo.remote_url, &self.remote_url),
+            (&repo.root_commit, &self.root_commit),
+        ];
+        recorded.iter().any(|(recorded, _)| recorded.is_some())
+            && recorded
+                .iter()
+                .all(|(recorded, found)| recorded.is_none() || recorded == found)
+    }
+}
+
+/// A file listing directories to skip when scanning, in the directory it applies to.
+const IGNORE_FILE_NAME: &str = ".gitlassoignore";
+
+/// Limits on how far `find_git_repos` looks for repositories.
+#[derive(Default)]
+pub struct ScanOptions {
+    /// How many directories deep to look, where the scanned directory's children are depth 1.
+    pub max_depth: Option<usize>,
+    /// Patterns for directories to skip, as in `.gitlassoignore`, relative to the scanned
+    /// directory.
+    pub exclude: Vec<String>,
+    /// Keep looking inside repositories for other repositories nested in them.
+    pub nested: bool,
+    /// Find the submodules of each repository.
+    pub submodules: bool,
+}
+
+/// Patterns for directories to skip, relative to a base directory. As in `.gitignore`, a pattern
+/// without a `/` matches a directory's name at any depth, and a pattern with a `/` matches its
+/// path relative to the base directory.
+struct IgnorePatterns {
+    base: PathBuf,
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl IgnorePatterns {
+    fn new<S: AsRef<str>>(base: &Path, patterns: &[S]) -> anyhow::Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+
+        for pattern in patterns {
+            let pattern = pattern.as_ref().trim().trim_end_matches('/');
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
+            }
+            let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("invalid ignore pattern '{}'", pattern))?;
+            if pattern.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+
+        Ok(IgnorePatterns {
+            base: base.to_owned(),
+            names: names.build()?,
+            paths: paths.build()?,
+        })
+    }
+
+    /// Read a `.gitlassoignore` file in a directory, if there is one.
+    fn read(dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = dir.join(IGNORE_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let str = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read '{}'", path.to_string_lossy()))?;
+        let lines: Vec<&str> = str.lines().collect();
+        let patterns = IgnorePatterns::new(dir, &lines)
+            .with_context(|| format!("in '{}'", path.to_string_lossy()))?;
+        Ok(Some(patterns))
+    }
+
+    fn is_match(&self, path: &Path) -> bool {
+        let name_matches = path
+            .file_name()
+            .is_some_and(|name| self.names.is_match(name));
+        let path_matches = path
+            .strip_prefix(&self.base)
+            .is_ok_and(|relative| self.paths.is_match(relative));
+        name_matches || path_matches
+    }
+}
+
+/// Whether a directory could be a repository, before asking git. This avoids opening every
+/// directory with git.
+fn might_be_git_repo(path: &Path) -> bool {
+    let looks_bare = || {
+        path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+    };
+    path.join(".git").exists() || looks_bare()
+}
+
+/// Open the repository at exactly this path, which may be a working tree or a bare repository.
+fn open_repo(path: &Path) -> Option<Repository> {
+    Repository::open_ext(path, RepositoryOpenFlags::NO_SEARCH, &[] as &[&OsStr]).ok()
+}
+
+fn discover_repo(path: &Path) -> Option<DiscoveredRepo> {
+    if !might_be_git_repo(path) {
+        return None;
+    }
+    let repo = open_repo(path)?;
+
+    // Without a `.git` entry this must be a git directory. Only bare repositories count: the
+    // git directory of a working tree elsewhere looks the same.
+    let is_bare = || {
+        repo.config()
+            .and_then(|config| config.get_bool("core.bare"))
+            .unwrap_or(false)
+    };
+    if !path.join(".git").exists() && !is_bare() {
+        return None;
+    }
+
+    let path = path.canonicalize().ok()?;
+    let kind = RepoKind::of(&repo, &path);
+
+    // A worktree's git directory has a `commondir` file pointing to the main repository's git
+    // directory: either the `.git` directory in its working tree, or the bare repository itself.
+    let main_repo = (kind == RepoKind::Worktree)
+        .then(|| {
+            let common_dir = fs::read_to_string(repo.path().join("commondir")).ok()?;
+            let common_dir = repo.path().join(common_dir.trim()).canonicalize().ok()?;
+            if common_dir.file_name() == Some(OsStr::new(".git")) {
+                common_dir.parent().map(Path::to_owned)
+            } else {
+                Some(common_dir)
+            }
+        })
+        .flatten();
+
+    Some(DiscoveredRepo {
+        path,
+        kind,
+        main_repo,
+        superproject: None,
+    })
+}
+
+/// Find the checked out submodules of a repository, and theirs in turn.
+fn find_submodules(superproject: &Path) -> Vec<DiscoveredRepo> {
+    let Ok(repo) = Repository::open(superproject) else {
+        return Vec::new();
+    };
+    let Ok(submodules) = repo.submodules() else {
+        return Vec::new();
+    };
+
+    let mut repos = Vec::new();
+    for submodule in submodules {
+        let Some(mut repo) = discover_repo(&superproject.join(submodule.path())) else {
+            // Not checked out
+            continue;
+        };
+        repo.superproject = Some(superproject.to_owned());
+        let path = repo.path.clone();
+        repos.push(repo);
+        repos.extend(find_submodules(&path));
+    }
+    repos
+}
+
+pub fn find_git_repos(
+    root_dir: &Path,
+    options: &ScanOptions,
+) -> anyhow::Result<Vec<DiscoveredRepo>> {
     // An explicit iterator & loop is used here to short circuit the recursion when
-    // a git repo is found.
+    // a git repo or an ignored directory is found.
+
+    let mut repos: Vec<DiscoveredRepo> = Vec::new();
 
-    let mut paths: Vec<PathBuf> = Vec::new();
+    // Discovered paths are canonical, so walk a canonical path for them to be compared with
+    let root_dir = &root_dir
+        .canonicalize()
+        .with_context(|| format!("could not find '{}'", root_dir.to_string_lossy()))?;
+
+    let exclude = IgnorePatterns::new(root_dir, &options.exclude)?;
+
+    // The `.gitlassoignore` files that apply to the current directory, with the depth of the
+    // directory each was found in.
+    let mut ignore_files: Vec<(usize, IgnorePatterns)> = Vec::new();
 
     // Iterate through directories
-    let mut it = WalkDir::new(root_dir)
-        .into_iter()
-        .filter_entry(|entry| entry.file_type().is_dir());
+    let mut walk_dir = WalkDir::new(root_dir);
+    if let Some(max_depth) = options.max_depth {
+        walk_dir = walk_dir.max_depth(max_depth);
+    }
+    let mut it = walk_dir.into_iter().filter_entry(|entry| {
+        entry.file_type().is_dir() && (entry.depth() == 0 || entry.file_name() != ".git")
+    });
+
+    // Submodules are found through their superproject rather than by looking inside it.
+    let mut submodule_paths: HashSet<PathBuf> = HashSet::new();
 
     loop {
         let entry = match it.next() {
@@ -24,14 +341,53 @@ pub fn find_git_repos(root_dir: &Path) -> Vec<PathBuf> {
             Some(Ok(entry)) => entry,
         };
 
-        // If this is a git repo, stop iterating through its children
-        if is_git_repo(entry.path()) {
-            if let Ok(full_path) = entry.path().canonicalize() {
-                paths.push(full_path);
+        // Forget ignore files from directories that have been left
+        while ignore_files
+            .last()
+            .is_some_and(|(depth, _)| *depth >= entry.depth())
+        {
+            ignore_files.pop();
+        }
+
+        // The scanned directory itself is never ignored
+        let ignored = entry.depth() > 0
+            && (exclude.is_match(entry.path())
+                || ignore_files.iter().any(|(_, p)| p.is_match(entry.path())));
+        if ignored {
+            it.skip_current_dir();
+            continue;
+        }
+
+        if submodule_paths.contains(entry.path()) {
+            it.skip_current_dir();
+            continue;
+        }
+
+        if let Some(repo) = discover_repo(entry.path()) {
+            // A bare repository has no working tree for anything to be nested in
+            let skip_children = !options.nested || repo.kind == RepoKind::Bare;
+            let submodules = if options.nested || options.submodules {
+                find_submodules(&repo.path)
+            } else {
+                Vec::new()
+            };
+            submodule_paths.extend(submodules.iter().map(|s| s.path.clone()));
+            repos.push(repo);
+            if options.submodules {
+                repos.extend(submodules);
+            }
+
+            // Stop iterating through the repo's children, unless looking for nested repos
+            if skip_children {
                 it.skip_current_dir();
+                continue;
             }
         }
+
+        if let Some(patterns) = IgnorePatterns::read(entry.path())? {
+            ignore_files.push((entry.depth(), patterns));
+        }
     }
 
-    return paths;
+    Ok(repos)
 }
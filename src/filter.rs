@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use crate::{
+    command::status::fetch_infos,
+    config::RepoConfig,
+    pattern::{matches_any, parse_patterns, RepoPattern},
+    predicate::Predicate,
+};
+
+/// Narrows the repositories a single invocation operates on, without changing the saved context.
+#[derive(Default)]
+pub struct RepoFilter {
+    /// Only include repositories with at least one of these tags.
+    tags: Vec<String>,
+    /// Only include repositories matching at least one of these patterns.
+    only: Vec<RepoPattern>,
+    /// Exclude repositories matching any of these patterns.
+    exclude: Vec<RepoPattern>,
+    /// Predicates on the git state of repositories, which must all match.
+    predicates: Vec<Predicate>,
+    /// The repositories matching `predicates`. These are found up front by `evaluate`, as
+    /// checking them requires opening each repository.
+    state_matches: Option<Vec<PathBuf>>,
+    /// A short description of each part of the filter for display.
+    description: Vec<String>,
+}
+
+impl RepoFilter {
+    pub fn new(
+        tags: &[String],
+        only: &[String],
+        exclude: &[String],
+        predicates: &[String],
+        dirty: bool,
+    ) -> anyhow::Result<Self> {
+        let mut filter = RepoFilter {
+            tags: tags.to_vec(),
+            only: parse_patterns(only)?,
+            exclude: parse_patterns(exclude)?,
+            ..RepoFilter::default()
+        };
+
+        for predicate in predicates {
+            filter.predicates.push(Predicate::parse(&[predicate])?);
+        }
+        if dirty {
+            filter.predicates.push(Predicate::parse(&["dirty"])?);
+        }
+
+        filter
+            .description
+            .extend(tags.iter().map(|tag| format!("tag:{}", tag)));
+        filter
+            .description
+            .extend(only.iter().map(|p| format!("only:{}", p)));
+        filter
+            .description
+            .extend(exclude.iter().map(|p| format!("exclude:{}", p)));
+        filter
+            .description
+            .extend(predicates.iter().map(|p| format!("where:{}", p)));
+        if dirty {
+            filter.description.push("dirty".to_owned());
+        }
+
+        Ok(filter)
+    }
+
+    /// Evaluate predicates on the git state of the repositories that pass the rest of the filter.
+    pub fn evaluate(&mut self, repositories: &[RepoConfig]) {
+        if self.predicates.is_empty() {
+            return;
+        }
+
+        let paths: Vec<PathBuf> = repositories
+            .iter()
+            .filter(|r| self.matches_config(r))
+            .map(|r| r.path.clone())
+            .collect();
+
+        let state_matches = fetch_infos(&paths)
+            .into_iter()
+            .filter_map(|(path, repo_info)| match repo_info {
+                Ok(info) => self
+                    .predicates
+                    .iter()
+                    .all(|p| p.matches(&info))
+                    .then_some(path),
+                Err(err) => {
+                    eprintln!("Error {}: {}", path.to_string_lossy(), err);
+                    None
+                }
+            })
+            .collect();
+
+        self.state_matches = Some(state_matches);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.description.is_empty()
+    }
+
+    pub fn matches(&self, repo: &RepoConfig) -> bool {
+        self.matches_config(repo)
+            && self
+                .state_matches
+                .as_ref()
+                .is_none_or(|paths| paths.contains(&repo.path))
+    }
+
+    /// Match the parts of the filter that only need the repository's configuration.
+    fn matches_config(&self, repo: &RepoConfig) -> bool {
+        (self.tags.is_empty() || self.tags.iter().any(|tag| repo.tags.contains(tag)))
+            && (self.only.is_empty() || matches_any(&self.only, repo))
+            && !matches_any(&self.exclude, repo)
+    }
+
+    /// A short description of the filter for display.
+    pub fn describe(&self) -> String {
+        self.description.join(" ")
+    }
+}
@@ -0,0 +1,51 @@
+use std::io::{stdout, IsTerminal, Write};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+use crossterm::{cursor, terminal, QueueableCommand};
+
+/// The conventional exit code for a process interrupted by SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INSTALL: Once = Once::new();
+
+/// Install a handler for SIGINT and SIGTERM that records the interruption rather than exiting,
+/// so that running commands can be stopped and the terminal restored. Code that installs the
+/// handler must poll `interrupted`. A second signal exits immediately.
+pub fn install() {
+    INSTALL.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                exit();
+            }
+        });
+        if let Err(err) = result {
+            eprintln!("could not install signal handler: {}", err);
+        }
+    });
+}
+
+/// Record an interruption that didn't come from a signal, e.g. Ctrl-C in raw mode.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Restore the terminal and exit. The terminal may have been left with the cursor hidden, line
+/// wrapping disabled or in raw mode.
+pub fn exit() -> ! {
+    let _ = terminal::disable_raw_mode();
+    let mut out = stdout();
+    if out.is_terminal() {
+        let _ = out
+            .queue(cursor::Show)
+            .and_then(|out| out.queue(terminal::EnableLineWrap))
+            .and_then(|out| out.flush());
+    }
+    process::exit(INTERRUPTED_EXIT_CODE)
+}
@@ -1,15 +1,27 @@
 use anyhow::Error;
 use clap::Parser;
-use cli_options::{Cli, Commands};
+use cli_options::{Cli, Commands, ContextCommands, OutputFormat, SerialArgs, TagCommands};
+use command::register::RegisterMode;
 use directories::ProjectDirs;
+use discover::ScanOptions;
+use filter::RepoFilter;
+use serial_run::SerialOptions;
 
 mod cli_options;
 mod command;
 mod config;
 mod discover;
+mod filter;
+mod interrupt;
+mod manifest;
 mod parallel_run;
 mod path;
+mod pattern;
+mod predicate;
+mod run_state;
 mod serial_run;
+mod summary;
+mod template;
 mod tui;
 
 fn main() -> anyhow::Result<()> {
@@ -18,23 +30,128 @@ fn main() -> anyhow::Result<()> {
     let project_dirs = ProjectDirs::from("", "", "GitLasso")
         .ok_or(Error::msg("could not find configuration directory"))?;
 
-    let repositories_path = project_dirs.config_dir().join("repositories");
+    let mut config = config::read(project_dirs.config_dir())?;
+    if cli.jobs.is_some() {
+        config.settings.jobs = cli.jobs;
+    }
+    if cli.timeout.is_some() {
+        config.settings.timeout = cli.timeout;
+    }
 
-    let config = config::read(&repositories_path)?;
+    let mut filter = RepoFilter::new(
+        &cli.filter.tags,
+        &cli.filter.only,
+        &cli.filter.exclude,
+        &cli.filter.predicates,
+        cli.filter.dirty,
+    )?;
+    filter.evaluate(&config.repositories);
+    config.filter = filter;
 
     match cli.command {
-        Some(Commands::Register { path }) => command::register::register(config, &path),
+        Some(Commands::Register {
+            path,
+            max_depth,
+            exclude,
+            nested,
+            submodules,
+            dry_run,
+            interactive,
+            rescan,
+        }) => {
+            let options = ScanOptions {
+                max_depth,
+                exclude: [config.settings.ignore.clone(), exclude].concat(),
+                nested,
+                submodules,
+            };
+            let mode = if dry_run {
+                RegisterMode::DryRun
+            } else if interactive {
+                RegisterMode::Interactive
+            } else {
+                RegisterMode::All
+            };
+            command::register::register(config, &path, options, mode, rescan)
+        }
         Some(Commands::Unregister { keep_context }) => {
             command::unregister::unregister(config, keep_context)
         }
+        Some(Commands::Clone { manifest, root }) => {
+            command::clone::clone(config, &manifest, root.as_deref())
+        }
+        Some(Commands::Export {
+            manifest_format,
+            root,
+            output,
+        }) => {
+            if cli.format != OutputFormat::Table {
+                anyhow::bail!(
+                    "'--format' is for the status summary: use 'export --manifest-format'"
+                );
+            }
+            command::export::export(config, manifest_format, root.as_deref(), output.as_deref())
+        }
+        Some(Commands::Relocate {
+            old_prefix,
+            new_prefix,
+        }) => command::relocate::relocate(config, &old_prefix, &new_prefix),
+        Some(Commands::Prune { dry_run }) => command::prune::prune(config, dry_run),
         Some(Commands::Fetch) => command::fetch::fetch(config),
         Some(Commands::Pull) => command::pull::pull(config),
-        Some(Commands::Git { args }) => command::git::run(config, &args),
-        Some(Commands::Exec { parallel, args }) => command::exec::run(config, parallel, &args),
-        Some(Commands::Context) => command::context::context_ui(config),
+        Some(Commands::Git { serial, args }) => {
+            command::git::run(config, serial_options(&serial), &args)
+        }
+        Some(Commands::Exec {
+            parallel,
+            label_streams,
+            stream,
+            serial,
+            shell,
+            args,
+        }) => command::exec::run(
+            config,
+            parallel,
+            label_streams,
+            stream,
+            serial_options(&serial),
+            shell,
+            &args,
+        ),
+        Some(Commands::Context { tags, command }) => match command {
+            Some(ContextCommands::Save { name }) => command::context::save(config, &name),
+            Some(ContextCommands::Use { name }) => command::context::use_saved(config, &name),
+            Some(ContextCommands::List) => command::context::list(config),
+            Some(ContextCommands::Delete { name }) => command::context::delete(config, &name),
+            Some(ContextCommands::Set { patterns }) => command::context::set(config, &patterns),
+            Some(ContextCommands::Add { patterns }) => command::context::add(config, &patterns),
+            Some(ContextCommands::Remove { patterns }) => {
+                command::context::remove(config, &patterns)
+            }
+            Some(ContextCommands::Where { predicate }) => {
+                command::context::select_where(config, &predicate)
+            }
+            Some(ContextCommands::All) => command::context::select(config, |_| true),
+            Some(ContextCommands::None) => command::context::select(config, |_| false),
+            Some(ContextCommands::Invert) => command::context::select(config, |r| !r.visible),
+            None if !tags.is_empty() => command::context::select_tags(config, &tags),
+            None => command::context::context_ui(config),
+        },
+        Some(Commands::Tag { command }) => match command {
+            TagCommands::Add { tag, repos } => command::tag::add(config, &tag, &repos),
+            TagCommands::Remove { tag, repos } => command::tag::remove(config, &tag, &repos),
+            TagCommands::List => command::tag::list(config),
+        },
         Some(Commands::Completions { shell, binary_name }) => {
             command::completions::completions(shell, &binary_name)
         }
-        None => command::status::status(config),
+        None => command::status::status(config, cli.format),
+    }
+}
+
+fn serial_options(args: &SerialArgs) -> SerialOptions {
+    SerialOptions {
+        fail_fast: args.fail_fast,
+        resume: args.resume,
     }
 }
@@ -0,0 +1,173 @@
+use std::fs::read_to_string;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use toml_edit::{value, Array, ArrayOfTables, DocumentMut, Item, Table};
+
+/// A list of repositories to clone, so that the same repositories can be set up on each machine.
+///
+/// ```toml
+/// [[repositories]]
+/// url = "git@example.com:me/payments.git"
+/// path = "work/payments"
+/// branch = "main"
+/// alias = "pay"
+/// tags = ["backend"]
+/// ```
+///
+/// Paths are relative to the directory the repositories are cloned into, and default to the
+/// last part of the URL.
+#[derive(Serialize)]
+pub struct Manifest {
+    pub repositories: Vec<ManifestRepo>,
+}
+
+#[derive(Serialize)]
+pub struct ManifestRepo {
+    pub url: String,
+    pub path: PathBuf,
+    /// The branch to check out, rather than the remote's default branch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let str = read_to_string(path)
+            .with_context(|| format!("failed to read '{}'", path.to_string_lossy()))?;
+
+        let document: DocumentMut = str
+            .parse()
+            .with_context(|| format!("failed to parse '{}'", path.to_string_lossy()))?;
+
+        let repositories = match document.get("repositories") {
+            None => Vec::new(),
+            Some(item) => item
+                .as_array_of_tables()
+                .with_context(|| "'repositories' should be an array of tables")?
+                .iter()
+                .map(ManifestRepo::from_table)
+                .collect::<anyhow::Result<Vec<ManifestRepo>>>()?,
+        };
+
+        Ok(Manifest { repositories })
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut repositories = ArrayOfTables::new();
+        for repo in &self.repositories {
+            repositories.push(repo.to_table());
+        }
+        let mut document = DocumentMut::new();
+        document.insert("repositories", Item::ArrayOfTables(repositories));
+        document.to_string()
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// Write the manifest as YAML. JSON strings are valid YAML scalars, so they are used for
+    /// quoting rather than needing a YAML library.
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        if self.repositories.is_empty() {
+            return Ok("repositories: []\n".to_owned());
+        }
+        let mut yaml = String::from("repositories:\n");
+        for repo in &self.repositories {
+            yaml += &format!("  - url: {}\n", serde_json::to_string(&repo.url)?);
+            yaml += &format!("    path: {}\n", serde_json::to_string(&repo.path)?);
+            if let Some(branch) = &repo.branch {
+                yaml += &format!("    branch: {}\n", serde_json::to_string(branch)?);
+            }
+            if let Some(alias) = &repo.alias {
+                yaml += &format!("    alias: {}\n", serde_json::to_string(alias)?);
+            }
+            if !repo.tags.is_empty() {
+                yaml += &format!("    tags: {}\n", serde_json::to_string(&repo.tags)?);
+            }
+        }
+        Ok(yaml)
+    }
+}
+
+impl ManifestRepo {
+    fn from_table(table: &Table) -> anyhow::Result<Self> {
+        let Some(url) = table.get("url").and_then(Item::as_str) else {
+            bail!("repository entry is missing a 'url'");
+        };
+
+        let path = match table.get("path") {
+            Some(item) => item
+                .as_str()
+                .map(PathBuf::from)
+                .with_context(|| format!("'{}' has a 'path' that isn't a string", url))?,
+            None => default_path(url)
+                .with_context(|| format!("'{}' needs a 'path' to clone to", url))?,
+        };
+
+        // Manifests are shared, so a path must stay inside the directory cloned into
+        let inside_root = path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !inside_root {
+            bail!(
+                "'{}' has the path '{}', which should be relative and not contain '..'",
+                url,
+                path.to_string_lossy()
+            );
+        }
+
+        Ok(ManifestRepo {
+            url: url.to_owned(),
+            path,
+            branch: table
+                .get("branch")
+                .and_then(Item::as_str)
+                .map(str::to_owned),
+            alias: table.get("alias").and_then(Item::as_str).map(str::to_owned),
+            tags: table
+                .get("tags")
+                .and_then(Item::as_array)
+                .map(|tags| {
+                    tags.iter()
+                        .filter_map(|t| t.as_str())
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.insert("url", value(&self.url));
+        table.insert("path", value(self.path.to_string_lossy().as_ref()));
+        if let Some(branch) = &self.branch {
+            table.insert("branch", value(branch));
+        }
+        if let Some(alias) = &self.alias {
+            table.insert("alias", value(alias));
+        }
+        if !self.tags.is_empty() {
+            table.insert("tags", value(self.tags.iter().collect::<Array>()));
+        }
+        table
+    }
+}
+
+/// The directory git would clone a URL into, e.g. `payments` for
+/// `git@example.com:me/payments.git`.
+fn default_path(url: &str) -> Option<PathBuf> {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()?
+        .trim_end_matches(".git");
+    (!name.is_empty()).then(|| PathBuf::from(name))
+}
@@ -1,127 +1,489 @@
-use crossterm::style::Stylize;
+use crossterm::style::{Color, StyledContent, Stylize};
 use crossterm::terminal::{self, size};
 use crossterm::{cursor, style, QueueableCommand};
-use std::collections::HashMap;
-use std::io::{self, stdout, Write};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, stdout, BufRead, BufReader, Read, Write};
 use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
 use std::sync::mpsc;
 use std::sync::mpsc::RecvTimeoutError;
-use std::thread;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use crate::config::queue_context_line;
-use crate::config::Config;
+use crate::config::{Config, Settings};
+use crate::interrupt;
 use crate::path::path_to_string;
+use crate::summary::{describe_exit, RunSummary};
+use crate::template::{CommandTemplate, RepoVariables};
 
 const SPINNER_CHARS: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
 #[derive(PartialEq)]
 enum ProcessStatus {
+    Queued,
     Running,
-    Finished(String),
-    Error(String),
+    Finished(Output),
+    /// The program exited unsuccessfully. The exit code is `None` if it was killed by a signal.
+    Error {
+        output: Output,
+        exit_code: Option<i32>,
+    },
+    FailedToStart(String),
+    /// The program was killed after running for longer than the timeout. Contains any output
+    /// produced before then.
+    TimedOut(Output),
+    /// The program was killed, or never started, because GitLasso was interrupted.
+    Cancelled,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// A line of output from a program, without its line ending.
+#[derive(PartialEq)]
+struct OutputLine {
+    stream: Stream,
+    text: String,
+}
+
+/// The lines written to stdout and stderr, interleaved in the order they were read.
+type Output = Vec<OutputLine>;
+
+/// Options for how `parallel_run` shows the output of each program.
+#[derive(Default)]
+pub struct OutputOptions {
+    /// Only show the output of programs that failed.
+    pub only_errors: bool,
+    /// Prefix each line with the stream it was written to.
+    pub label_streams: bool,
+    /// Print each line as soon as it is written, prefixed by the repository name, rather than
+    /// showing progress and then each repository's output.
+    pub stream: bool,
+}
+
+/// In streaming mode, how to print the lines of a program's output as they arrive.
+#[derive(Clone)]
+struct Echo {
+    prefix: StyledContent<String>,
+    label_streams: bool,
+}
+
+/// Colours used to tell repositories apart in streaming mode.
+const STREAM_COLORS: [Color; 6] = [
+    Color::DarkCyan,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+    Color::DarkRed,
+];
+
+/// How often running programs are checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
 type ProcessStatuses = HashMap<PathBuf, ProcessStatus>;
 
-/// Run a program on all selected repositories in parallel. Show a spinner for each repository as
-/// the program is running, and then show any output.
+/// A program to run, identified by the path of the repository it is for.
+pub struct Job {
+    pub path: PathBuf,
+    pub command: Command,
+}
+
+/// Run a program on all selected repositories in parallel, and summarise the results.
 pub fn parallel_run(
     config: Config,
-    program: &str,
-    args: &[String],
-    only_errors: bool,
+    template: &CommandTemplate,
+    options: OutputOptions,
 ) -> anyhow::Result<()> {
     let paths = config.visible_repos();
+    let variables = RepoVariables::collect(&config, &paths);
+    let jobs = paths
+        .into_iter()
+        .map(|path| Job {
+            command: variables[&path].command(&path, template),
+            path,
+        })
+        .collect();
+
+    queue_context_line(stdout(), &config)?;
+    run_jobs(&config.settings, jobs, options)?.finish()
+}
+
+/// Run jobs in parallel. Show a spinner for each repository as its program is running, and then
+/// show any output.
+///
+/// At most `settings.jobs` programs run at once. The rest are queued and started as running
+/// programs finish. Programs running for longer than `settings.timeout` are killed.
+///
+/// In streaming mode output is printed line by line as it arrives instead.
+///
+/// If interrupted, all running programs are killed and the remaining queue is cancelled.
+pub fn run_jobs(
+    settings: &Settings,
+    jobs: Vec<Job>,
+    options: OutputOptions,
+) -> anyhow::Result<RunSummary> {
+    let paths: Vec<PathBuf> = jobs.iter().map(|job| job.path.clone()).collect();
+
+    interrupt::install();
 
     let mut results: ProcessStatuses = paths
         .iter()
-        .map(|p| (p.clone(), ProcessStatus::Running))
+        .map(|p| (p.clone(), ProcessStatus::Queued))
         .collect();
 
-    // Each thread sends its result back through this channel.
+    // Each worker takes repositories from this queue until it is empty.
+    let queue = Arc::new(Mutex::new(jobs.into_iter().collect::<VecDeque<Job>>()));
+
+    // Each worker sends status updates back through this channel.
     let (tx, rx) = mpsc::channel();
 
-    // For each repo, kick off a thread executing the command.
-    for path in &paths {
-        let thread_path = path.clone();
+    let echoes: HashMap<PathBuf, Echo> = if options.stream {
+        stream_prefixes(&paths)
+            .into_iter()
+            .map(|(path, prefix)| {
+                let echo = Echo {
+                    prefix,
+                    label_streams: options.label_streams,
+                };
+                (path, echo)
+            })
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let echoes = Arc::new(echoes);
+
+    let worker_count = match settings.jobs {
+        Some(jobs) if jobs > 0 => jobs.min(paths.len()),
+        _ => paths.len(),
+    };
+
+    for _ in 0..worker_count {
+        let thread_queue = Arc::clone(&queue);
         let thread_tx = tx.clone();
-        let thread_program = program.to_string();
-        let thread_args = Vec::from(args);
-        thread::spawn(move || {
-            let output = std::process::Command::new(thread_program)
-                .args(&thread_args)
-                .current_dir(&thread_path)
-                .output();
-
-            let result = match output {
-                Ok(output) => {
-                    if output.status.success() {
-                        ProcessStatus::Finished(
-                            String::from_utf8_lossy(&output.stdout).into_owned(),
-                        )
-                    } else {
-                        ProcessStatus::Error(String::from_utf8_lossy(&output.stderr).into_owned())
-                    }
-                }
-                Err(err) => ProcessStatus::Error(err.to_string()),
+        let timeout = settings.timeout;
+        let thread_echoes = Arc::clone(&echoes);
+        thread::spawn(move || loop {
+            // Release the lock before running the program so other workers can proceed.
+            let next = thread_queue.lock().unwrap().pop_front();
+            let Some(Job { path, command }) = next else {
+                break;
             };
 
-            // This should only fail to send when the receiver has hung up.
+            if interrupt::interrupted() {
+                thread_tx
+                    .send((path, ProcessStatus::Cancelled))
+                    .expect("could not send");
+                continue;
+            }
+
+            // These should only fail to send when the receiver has hung up.
             // In theory this cannot happen.
             thread_tx
-                .send((thread_path.clone(), result))
+                .send((path.clone(), ProcessStatus::Running))
                 .expect("could not send");
+
+            let echo = thread_echoes.get(&path).cloned();
+            let result = run_program(command, timeout, echo);
+
+            thread_tx.send((path, result)).expect("could not send");
         });
     }
 
-    // This has been cloned for each thread, so drop the original.
-    // When the threads have all dropped their clone, the channel will close.
+    // This has been cloned for each worker, so drop the original.
+    // When the workers have all dropped their clone, the channel will close.
     drop(tx);
 
+    let mut out = stdout();
+
+    if options.stream {
+        out.flush()?;
+
+        // Lines are printed by the workers, so just report how each program finished.
+        for (path, result) in rx {
+            if let (Some(echo), Some(status)) = (echoes.get(&path), describe_status(&result)) {
+                let mut out = out.lock();
+                out.queue(style::PrintStyledContent(echo.prefix.clone()))?
+                    .queue(style::PrintStyledContent(status.dark_grey()))?
+                    .queue(style::Print("\n"))?
+                    .flush()?;
+            }
+            results.insert(path, result);
+        }
+
+        return Ok(summarise(paths, results));
+    }
+
     let (width, height) = size()?;
 
     // Show a compact spinner if there isn't enough space to show a spinner for each repo.
     let compact = paths.len() >= height as usize;
 
-    wait_for_results(config, &paths, &mut results, rx, compact)?;
+    wait_for_results(&paths, &mut results, rx, compact)?;
 
     // Print out output
-    for path in paths {
-        match results.get(&path) {
-            Some(ProcessStatus::Error(err)) => {
-                let header = format!("{:width$}", path_to_string(&path), width = width as usize)
-                    .on_red()
-                    .black();
-                stdout()
-                    .queue(style::Print("\n"))?
-                    .queue(style::PrintStyledContent(header))?
-                    .queue(style::Print("\n"))?
-                    .queue(style::Print(err))?;
+    for path in &paths {
+        let path_string = path_to_string(path);
+        match results.get(path) {
+            Some(ProcessStatus::Error { output, exit_code }) => {
+                let header = format!("{} ({})", path_string, describe_exit(*exit_code));
+                queue_header(&out, header, width, Color::Red)?;
+                queue_output(&out, output, options.label_streams)?;
             }
-            Some(ProcessStatus::Finished(out)) => {
-                if !out.is_empty() && !only_errors {
-                    let header =
-                        format!("{:width$}", path_to_string(&path), width = width as usize)
-                            .on_white()
-                            .black();
-                    stdout()
-                        .queue(style::Print("\n"))?
-                        .queue(style::PrintStyledContent(header))?
-                        .queue(style::Print("\n"))?
-                        .queue(style::Print(out))?;
-                }
+            Some(ProcessStatus::FailedToStart(err)) => {
+                queue_header(&out, path_string, width, Color::Red)?;
+                out.queue(style::Print(format!("{}\n", err)))?;
+            }
+            Some(ProcessStatus::TimedOut(output)) => {
+                let header = format!("{} (timed out)", path_string);
+                queue_header(&out, header, width, Color::DarkYellow)?;
+                queue_output(&out, output, options.label_streams)?;
+            }
+            Some(ProcessStatus::Finished(output)) if !output.is_empty() && !options.only_errors => {
+                queue_header(&out, path_string, width, Color::White)?;
+                queue_output(&out, output, options.label_streams)?;
             }
             _ => {}
         }
     }
-    stdout().flush()?;
+    out.flush()?;
+
+    Ok(summarise(paths, results))
+}
+
+fn summarise(paths: Vec<PathBuf>, mut results: ProcessStatuses) -> RunSummary {
+    let mut summary = RunSummary::default();
+    for path in paths {
+        match results.remove(&path) {
+            Some(ProcessStatus::Finished(_)) => summary.succeeded.push(path),
+            Some(ProcessStatus::Error { exit_code, .. }) => {
+                summary.failed.push((path, describe_exit(exit_code)))
+            }
+            Some(ProcessStatus::FailedToStart(err)) => summary
+                .failed
+                .push((path, format!("failed to start: {}", err))),
+            Some(ProcessStatus::TimedOut(_)) => summary.failed.push((path, "timed out".to_owned())),
+            _ => summary.cancelled.push(path),
+        }
+    }
+    summary
+}
+
+fn queue_header(
+    mut out: impl QueueableCommand,
+    header: String,
+    width: u16,
+    background: Color,
+) -> io::Result<()> {
+    let header = format!("{:width$}", header, width = width as usize)
+        .on(background)
+        .black();
+    out.queue(style::Print("\n"))?
+        .queue(style::PrintStyledContent(header))?
+        .queue(style::Print("\n"))?;
+    Ok(())
+}
+
+fn queue_output(
+    mut out: impl QueueableCommand,
+    output: &Output,
+    label_streams: bool,
+) -> io::Result<()> {
+    for line in output {
+        queue_line(&mut out, line, label_streams)?;
+    }
+    Ok(())
+}
 
+fn queue_line(
+    out: &mut impl QueueableCommand,
+    line: &OutputLine,
+    label_streams: bool,
+) -> io::Result<()> {
+    if label_streams {
+        out.queue(style::PrintStyledContent(match line.stream {
+            Stream::Stdout => "out│ ".dark_grey(),
+            Stream::Stderr => "err│ ".dark_red(),
+        }))?;
+    }
+    out.queue(style::Print(&line.text))?
+        .queue(style::Print("\n"))?;
     Ok(())
 }
 
+/// Build a coloured prefix for each repository, padded so that the output lines up, in the
+/// style of `docker compose logs`.
+fn stream_prefixes(paths: &[PathBuf]) -> Vec<(PathBuf, StyledContent<String>)> {
+    let names: Vec<String> = paths
+        .iter()
+        .map(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path_to_string(path))
+        })
+        .collect();
+    let name_width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+
+    paths
+        .iter()
+        .zip(names)
+        .enumerate()
+        .map(|(i, (path, name))| {
+            let prefix = format!("{:name_width$} | ", name, name_width = name_width)
+                .with(STREAM_COLORS[i % STREAM_COLORS.len()]);
+            (path.clone(), prefix)
+        })
+        .collect()
+}
+
+/// Describe a finished program in streaming mode.
+fn describe_status(status: &ProcessStatus) -> Option<String> {
+    match status {
+        ProcessStatus::Finished(_) => Some("exited with exit code 0".to_owned()),
+        ProcessStatus::Error { exit_code, .. } => {
+            Some(format!("exited with {}", describe_exit(*exit_code)))
+        }
+        ProcessStatus::FailedToStart(err) => Some(format!("failed to start: {}", err)),
+        ProcessStatus::TimedOut(_) => Some("timed out".to_owned()),
+        ProcessStatus::Cancelled => Some("cancelled".to_owned()),
+        ProcessStatus::Queued | ProcessStatus::Running => None,
+    }
+}
+
+fn run_program(
+    mut command: Command,
+    timeout: Option<Duration>,
+    echo: Option<Echo>,
+) -> ProcessStatus {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // Run each program in a new session, and so its own process group, so that it can be killed
+    // along with any processes it starts. Without a controlling terminal, git and ssh fail
+    // rather than prompt for credentials or to confirm a host key, which would otherwise wait
+    // forever without being shown.
+    #[cfg(unix)]
+    unsafe {
+        std::os::unix::process::CommandExt::pre_exec(&mut command, || {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    if std::env::var_os("GIT_TERMINAL_PROMPT").is_none() {
+        command.env("GIT_TERMINAL_PROMPT", "0");
+    }
+
+    let child = command.spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => return ProcessStatus::FailedToStart(err.to_string()),
+    };
+
+    let output = Arc::new(Mutex::new(Output::new()));
+    let stdout_handle = read_lines(
+        child.stdout.take().unwrap(),
+        Stream::Stdout,
+        &output,
+        echo.clone(),
+    );
+    let stderr_handle = read_lines(child.stderr.take().unwrap(), Stream::Stderr, &output, echo);
+    let take_output = || std::mem::take(&mut *output.lock().unwrap());
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    // Poll the child rather than waiting on it, so that it can be killed at the deadline.
+    let exit_status = loop {
+        match child.try_wait() {
+            Ok(Some(exit_status)) => break exit_status,
+            Ok(None) => {}
+            Err(err) => return ProcessStatus::FailedToStart(err.to_string()),
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            kill(&mut child);
+            // Don't wait for the readers: the pipes may still be held open by grandchildren.
+            return ProcessStatus::TimedOut(take_output());
+        }
+
+        if interrupt::interrupted() {
+            kill(&mut child);
+            return ProcessStatus::Cancelled;
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    stdout_handle.join().unwrap();
+    stderr_handle.join().unwrap();
+
+    if exit_status.success() {
+        ProcessStatus::Finished(take_output())
+    } else {
+        ProcessStatus::Error {
+            output: take_output(),
+            exit_code: exit_status.code(),
+        }
+    }
+}
+
+/// Kill a program started by `run_program` along with its process group.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Read lines from a pipe on another thread, appending them to the shared output, or printing
+/// them immediately in streaming mode.
+fn read_lines(
+    reader: impl Read + Send + 'static,
+    stream: Stream,
+    output: &Arc<Mutex<Output>>,
+    echo: Option<Echo>,
+) -> JoinHandle<()> {
+    let output = Arc::clone(output);
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut buffer = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut buffer) {
+            if n == 0 {
+                break;
+            }
+            let text = String::from_utf8_lossy(&buffer)
+                .trim_end_matches(['\n', '\r'])
+                .to_owned();
+            let line = OutputLine { stream, text };
+            match &echo {
+                Some(echo) => {
+                    // Lock stdout so that lines from different programs don't interleave.
+                    let mut out = stdout().lock();
+                    let _ = out
+                        .queue(style::PrintStyledContent(echo.prefix.clone()))
+                        .and_then(|out| queue_line(out, &line, echo.label_streams))
+                        .and_then(|_| out.flush());
+                }
+                None => output.lock().unwrap().push(line),
+            }
+            buffer.clear();
+        }
+    })
+}
+
 fn wait_for_results(
-    config: Config,
     paths: &[PathBuf],
     results: &mut HashMap<PathBuf, ProcessStatus>,
     rx: mpsc::Receiver<(PathBuf, ProcessStatus)>,
@@ -133,7 +495,6 @@ fn wait_for_results(
     // Long repo names can muck up the redraw
     out.queue(terminal::DisableLineWrap)?.flush()?;
 
-    queue_context_line(&out, &config)?;
     out.queue(cursor::Hide)?;
     if !compact {
         // An initial print, so that when the cursor is moved up it goes to the correct place.
@@ -186,13 +547,18 @@ fn queue_update_progress_compact(
         SPINNER_CHARS[spinner_index].bold(),
     ))?;
     let total = paths.len();
-    let finished = paths
-        .into_iter()
-        .filter(|p| results.get(*p) != Some(&ProcessStatus::Running))
-        .count();
+    let count = |f: fn(&ProcessStatus) -> bool| {
+        paths
+            .iter()
+            .filter(|p| results.get(*p).is_some_and(f))
+            .count()
+    };
+    let queued = count(|s| *s == ProcessStatus::Queued);
+    let running = count(|s| *s == ProcessStatus::Running);
+    let finished = total - queued - running;
     out.queue(style::Print(format!(
-        " running [{}/{} complete]",
-        finished, total
+        " running [{} running, {} queued, {}/{} complete]",
+        running, queued, finished, total
     )))?;
     Ok(())
 }
@@ -207,8 +573,11 @@ fn queue_update_progress(
         if let Some(result) = results.get(path) {
             out.queue(style::PrintStyledContent(match *result {
                 ProcessStatus::Finished(_) => '✓'.dark_green(),
+                ProcessStatus::Queued => '·'.dark_grey(),
                 ProcessStatus::Running => SPINNER_CHARS[spinner_index].bold(),
-                ProcessStatus::Error(_) => 'X'.dark_red(),
+                ProcessStatus::Error { .. } | ProcessStatus::FailedToStart(_) => 'X'.dark_red(),
+                ProcessStatus::TimedOut(_) => '!'.dark_yellow(),
+                ProcessStatus::Cancelled => '-'.dark_grey(),
             }))?
             .queue(style::Print(format!(" {}\n", path_to_string(path))))?;
         }
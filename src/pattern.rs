@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+use crate::config::RepoConfig;
+
+/// A pattern selecting registered repositories. Patterns are one of:
+///
+/// - `re:<regex>`, a regular expression searched for in the repository path.
+/// - A path glob, if the pattern contains a `/`, starts with `~`, or is `.` or `..`. `*` does not
+///   match across directories, but `**` does.
+/// - Otherwise a glob matched against the repository name or alias.
+pub enum RepoPattern {
+    Path(GlobMatcher),
+    Name(GlobMatcher),
+    Regex(Regex),
+}
+
+impl RepoPattern {
+    pub fn parse(pattern: &str) -> anyhow::Result<Self> {
+        if let Some(regex) = pattern.strip_prefix("re:") {
+            let regex = Regex::new(regex).with_context(|| format!("invalid regex '{}'", regex))?;
+            return Ok(RepoPattern::Regex(regex));
+        }
+
+        if pattern.contains('/') || pattern.starts_with('~') || pattern == "." || pattern == ".." {
+            let path = expand_path(pattern);
+            let glob = GlobBuilder::new(&path.to_string_lossy())
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("invalid path pattern '{}'", pattern))?;
+            return Ok(RepoPattern::Path(glob.compile_matcher()));
+        }
+
+        let glob = GlobBuilder::new(pattern)
+            .build()
+            .with_context(|| format!("invalid pattern '{}'", pattern))?;
+        Ok(RepoPattern::Name(glob.compile_matcher()))
+    }
+
+    pub fn matches(&self, repo: &RepoConfig) -> bool {
+        match self {
+            RepoPattern::Path(glob) => glob.is_match(&repo.path),
+            RepoPattern::Name(glob) => {
+                glob.is_match(repo.name()) || repo.alias.as_ref().is_some_and(|a| glob.is_match(a))
+            }
+            RepoPattern::Regex(regex) => regex.is_match(&repo.path.to_string_lossy()),
+        }
+    }
+}
+
+/// Parse a list of patterns, returning a matcher for any of them.
+pub fn parse_patterns(patterns: &[String]) -> anyhow::Result<Vec<RepoPattern>> {
+    patterns.iter().map(|p| RepoPattern::parse(p)).collect()
+}
+
+pub fn matches_any(patterns: &[RepoPattern], repo: &RepoConfig) -> bool {
+    patterns.iter().any(|p| p.matches(repo))
+}
+
+/// Expand a leading `~` to the home directory and make relative paths absolute. Paths that exist
+/// are canonicalized so that they match registered paths.
+fn expand_path(pattern: &str) -> PathBuf {
+    let path = match (pattern.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home_dir)) => home_dir.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(pattern),
+    };
+
+    if let Ok(canonical) = path.canonicalize() {
+        return PathBuf::from(globset::escape(&canonical.to_string_lossy()));
+    }
+
+    match std::env::current_dir() {
+        Ok(current_dir) if path.is_relative() => current_dir.join(strip_dot(&path)),
+        _ => path,
+    }
+}
+
+fn strip_dot(path: &Path) -> &Path {
+    path.strip_prefix(".").unwrap_or(path)
+}
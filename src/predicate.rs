@@ -0,0 +1,106 @@
+use anyhow::{bail, Context};
+use globset::{Glob, GlobMatcher};
+
+use crate::command::status::RepoInfo;
+
+/// A condition on the live state of a repository, e.g. `dirty and branch=release/*`.
+///
+/// Conditions are combined with `and` and `or`, where `and` binds more tightly, and can be
+/// negated with `not`. Adjacent conditions without an operator are combined with `and`.
+pub struct Predicate {
+    /// The predicate in disjunctive normal form: it matches if every term of any group matches.
+    groups: Vec<Vec<Term>>,
+}
+
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+enum Condition {
+    Dirty,
+    Clean,
+    Ahead,
+    Behind,
+    NoUpstream,
+    Branch(GlobMatcher),
+}
+
+/// The conditions accepted by `Predicate::parse`, for help and error messages.
+pub const CONDITIONS: &str = "dirty, clean, ahead, behind, no-upstream, branch=<glob>";
+
+impl Predicate {
+    pub fn parse<S: AsRef<str>>(words: &[S]) -> anyhow::Result<Self> {
+        let mut groups = vec![Vec::new()];
+        let mut negated = false;
+
+        for word in words.iter().flat_map(|w| w.as_ref().split_whitespace()) {
+            match word {
+                "and" | "&&" => {}
+                "or" | "||" => {
+                    if negated || groups.last().is_some_and(Vec::is_empty) {
+                        bail!("expected a condition before 'or'");
+                    }
+                    groups.push(Vec::new());
+                }
+                "not" | "!" => negated = !negated,
+                _ => {
+                    let condition = Condition::parse(word)?;
+                    groups.last_mut().unwrap().push(Term { negated, condition });
+                    negated = false;
+                }
+            }
+        }
+
+        if negated || groups.iter().any(Vec::is_empty) {
+            bail!(
+                "incomplete predicate: expected a condition ({})",
+                CONDITIONS
+            );
+        }
+
+        Ok(Predicate { groups })
+    }
+
+    pub fn matches(&self, info: &RepoInfo) -> bool {
+        self.groups
+            .iter()
+            .any(|group| group.iter().all(|term| term.matches(info)))
+    }
+}
+
+impl Term {
+    fn matches(&self, info: &RepoInfo) -> bool {
+        self.condition.matches(info) != self.negated
+    }
+}
+
+impl Condition {
+    fn parse(word: &str) -> anyhow::Result<Self> {
+        if let Some(glob) = word.strip_prefix("branch=") {
+            let glob =
+                Glob::new(glob).with_context(|| format!("invalid branch glob '{}'", glob))?;
+            return Ok(Condition::Branch(glob.compile_matcher()));
+        }
+
+        Ok(match word {
+            "dirty" => Condition::Dirty,
+            "clean" => Condition::Clean,
+            "ahead" => Condition::Ahead,
+            "behind" => Condition::Behind,
+            "no-upstream" => Condition::NoUpstream,
+            _ => bail!("unknown condition '{}' (expected {})", word, CONDITIONS),
+        })
+    }
+
+    fn matches(&self, info: &RepoInfo) -> bool {
+        match self {
+            Condition::Dirty => !info.status.is_clean(),
+            Condition::Clean => info.status.is_clean(),
+            Condition::Ahead => info.ahead_behind.is_some_and(|(ahead, _)| ahead > 0),
+            Condition::Behind => info.ahead_behind.is_some_and(|(_, behind)| behind > 0),
+            Condition::NoUpstream => info.upstream.is_none(),
+            Condition::Branch(glob) => glob.is_match(&info.branch_name),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs::{create_dir_all, read_to_string, remove_file, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use toml_edit::{value, Array, DocumentMut, Item};
+
+/// The file, next to the configuration file, recording where the last serial run stopped.
+const STATE_FILE_NAME: &str = "last-run.toml";
+
+/// Where a serial run stopped, so that `--resume` can restart it from that repository.
+pub struct RunState {
+    /// The program and its arguments.
+    pub command: Vec<String>,
+    /// The first repository where the command failed or was not run.
+    pub stopped_at: PathBuf,
+}
+
+fn state_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(STATE_FILE_NAME)
+}
+
+impl RunState {
+    /// Read the state of the last run, if it stopped early.
+    pub fn read(config_path: &Path) -> anyhow::Result<Option<RunState>> {
+        let str = match read_to_string(state_path(config_path)) {
+            Ok(str) => str,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_context(|| "failed to read the last run"),
+        };
+
+        let document: DocumentMut = str
+            .parse()
+            .with_context(|| "failed to parse the last run")?;
+
+        let command = document
+            .get("command")
+            .and_then(Item::as_array)
+            .map(|array| {
+                array
+                    .iter()
+                    .filter_map(|v| v.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .with_context(|| "the last run has no command")?;
+        let stopped_at = document
+            .get("stopped_at")
+            .and_then(Item::as_str)
+            .map(PathBuf::from)
+            .with_context(|| "the last run has no repository to resume from")?;
+
+        Ok(Some(RunState {
+            command,
+            stopped_at,
+        }))
+    }
+
+    pub fn write(&self, config_path: &Path) -> anyhow::Result<()> {
+        let mut document = DocumentMut::new();
+        document.insert("command", value(Array::from_iter(&self.command)));
+        document.insert(
+            "stopped_at",
+            value(self.stopped_at.to_string_lossy().as_ref()),
+        );
+
+        let path = state_path(config_path);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut file = File::create(&path).with_context(|| "failed to create the last run")?;
+        file.write_all(document.to_string().as_bytes())
+            .with_context(|| "failed to write the last run")
+    }
+
+    /// Forget the last run, after a run that completed in every repository.
+    pub fn clear(config_path: &Path) -> anyhow::Result<()> {
+        match remove_file(state_path(config_path)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                Err(err).with_context(|| "failed to remove the last run")
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,21 +1,94 @@
+use anyhow::bail;
 use crossterm::{
     style::{self, Stylize},
     terminal::size,
     QueueableCommand,
 };
 
-use crate::{config::queue_context_line, config::Config, path::path_to_string};
+use crate::{
+    config::queue_context_line,
+    config::Config,
+    interrupt,
+    path::path_to_string,
+    run_state::RunState,
+    summary::{describe_exit, RunSummary},
+    template::{CommandTemplate, RepoVariables},
+};
 
 use std::io::{self, stdout, Write};
+use std::thread;
+use std::time::Duration;
+
+/// How often the running program is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Options controlling what happens when the command fails.
+#[derive(Default)]
+pub struct SerialOptions {
+    /// Stop at the first repository where the command fails, rather than carrying on.
+    pub fail_fast: bool,
+    /// Start from the repository where the last run of the same command stopped.
+    pub resume: bool,
+}
+
+/// Run a program in each repository in turn, showing its output as it runs.
+///
+/// If the run doesn't complete in every repository, the first repository where it failed or
+/// was cancelled is recorded so that it can be resumed from there.
+pub fn serial_run(
+    config: Config,
+    template: &CommandTemplate,
+    options: SerialOptions,
+) -> anyhow::Result<()> {
+    let mut paths = config.visible_repos();
+    let words = template.words();
 
-pub fn serial_run(config: Config, program: &str, args: &[String]) -> anyhow::Result<()> {
-    let paths = config.visible_repos();
+    let resumed_from = if options.resume {
+        let Some(state) = RunState::read(&config.path)? else {
+            bail!("there is no failed run to resume");
+        };
+        if state.command != words {
+            bail!(
+                "the last run to fail was '{}', not '{}'",
+                state.command.join(" "),
+                words.join(" ")
+            );
+        }
+        let Some(start) = paths.iter().position(|path| *path == state.stopped_at) else {
+            bail!(
+                "'{}', where the last run stopped, is not selected",
+                path_to_string(&state.stopped_at)
+            );
+        };
+        paths.drain(..start);
+        Some(state.stopped_at)
+    } else {
+        None
+    };
+
+    let variables = RepoVariables::collect(&config, &paths);
+
+    interrupt::install();
 
     queue_context_line(stdout(), &config)?;
+    if let Some(path) = resumed_from {
+        stdout()
+            .queue(style::PrintStyledContent(
+                format!("resuming from {}", path_to_string(&path)).dark_grey(),
+            ))?
+            .queue(style::Print("\n"))?;
+    }
 
     let (width, _) = size()?;
 
-    for path in &paths {
+    let mut summary = RunSummary::default();
+
+    for (i, path) in paths.iter().enumerate() {
+        if options.fail_fast && !summary.failed.is_empty() {
+            summary.skipped.extend_from_slice(&paths[i..]);
+            break;
+        }
+
         let header = format!("{:width$}", path_to_string(path), width = width as usize)
             .on_white()
             .black();
@@ -26,12 +99,22 @@ pub fn serial_run(config: Config, program: &str, args: &[String]) -> anyhow::Res
             .queue(style::Print("\n"))?
             .flush()?;
 
-        let mut command = std::process::Command::new(program)
-            .args(args)
-            .current_dir(&path)
+        let command = variables[path]
+            .command(path, template)
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
-            .spawn()?;
+            .spawn();
+
+        let mut command = match command {
+            Ok(command) => command,
+            Err(err) => {
+                eprintln!("failed to start '{}': {}", words[0], err);
+                summary
+                    .failed
+                    .push((path.clone(), format!("failed to start: {}", err)));
+                continue;
+            }
+        };
 
         let mut stdout = command.stdout.take().unwrap();
         let mut stderr = command.stderr.take().unwrap();
@@ -44,12 +127,51 @@ pub fn serial_run(config: Config, program: &str, args: &[String]) -> anyhow::Res
             let _ = io::copy(&mut stderr, &mut io::stderr());
         });
 
+        // Poll the command rather than waiting on it, so that it can be killed if interrupted.
+        let exit_status = loop {
+            if let Some(exit_status) = command.try_wait()? {
+                break exit_status;
+            }
+            if interrupt::interrupted() {
+                break command.kill().and_then(|_| command.wait())?;
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        // Repositories the command was running in when interrupted count as cancelled.
+        // The output threads are not joined, as the pipes may be held open by grandchildren.
+        if interrupt::interrupted() {
+            summary.cancelled.extend_from_slice(&paths[i..]);
+            break;
+        }
+
         stdout_handle.join().unwrap();
         stderr_handle.join().unwrap();
 
-        command.wait()?;
+        if exit_status.success() {
+            summary.succeeded.push(path.clone());
+        } else {
+            summary
+                .failed
+                .push((path.clone(), describe_exit(exit_status.code())));
+        }
     }
 
     stdout().flush()?;
-    Ok(())
+
+    let stopped_at = summary
+        .failed
+        .first()
+        .map(|(path, _)| path)
+        .or(summary.cancelled.first());
+    match stopped_at {
+        Some(path) => RunState {
+            command: words,
+            stopped_at: path.clone(),
+        }
+        .write(&config.path)?,
+        None => RunState::clear(&config.path)?,
+    }
+
+    summary.finish()
 }
@@ -0,0 +1,97 @@
+use std::io::{self, stdout, Write};
+use std::path::PathBuf;
+use std::process;
+
+use crossterm::style::{Print, PrintStyledContent, Stylize};
+use crossterm::QueueableCommand;
+
+use crate::{interrupt, path::path_to_string};
+
+/// The exit code when the command failed in any repository.
+const FAILED_EXIT_CODE: i32 = 1;
+
+/// The outcome of running a command in each repository.
+#[derive(Default)]
+pub struct RunSummary {
+    pub succeeded: Vec<PathBuf>,
+    /// Repositories where the command failed, with a description of the failure.
+    pub failed: Vec<(PathBuf, String)>,
+    /// Repositories where the command was killed or never run because GitLasso was interrupted.
+    pub cancelled: Vec<PathBuf>,
+    /// Repositories where the command was not run because it failed in an earlier one.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl RunSummary {
+    /// Print the summary and finish the run. This exits if the run was interrupted or the
+    /// command failed in any repository, as the summary has already described the failures.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let mut out = stdout();
+        self.queue(&mut out)?;
+        out.flush()?;
+
+        if !self.cancelled.is_empty() || interrupt::interrupted() {
+            interrupt::exit();
+        }
+
+        if !self.failed.is_empty() {
+            process::exit(FAILED_EXIT_CODE);
+        }
+
+        Ok(())
+    }
+
+    fn queue(&self, mut out: impl QueueableCommand) -> io::Result<()> {
+        let mut counts = vec![format!("{} succeeded", self.succeeded.len()).dark_green()];
+        if !self.failed.is_empty() {
+            counts.push(format!("{} failed", self.failed.len()).red());
+        }
+        if !self.cancelled.is_empty() {
+            counts.push(format!("{} cancelled", self.cancelled.len()).dark_grey());
+        }
+        if !self.skipped.is_empty() {
+            counts.push(format!("{} skipped", self.skipped.len()).dark_grey());
+        }
+
+        out.queue(Print("\n"))?;
+        if !self.cancelled.is_empty() || interrupt::interrupted() {
+            out.queue(PrintStyledContent("Interrupted: ".bold()))?;
+        }
+        for (i, count) in counts.into_iter().enumerate() {
+            if i > 0 {
+                out.queue(Print(", "))?;
+            }
+            out.queue(PrintStyledContent(count))?;
+        }
+        out.queue(Print("\n"))?;
+
+        for (path, reason) in &self.failed {
+            out.queue(PrintStyledContent(
+                format!("  {}: {}", path_to_string(path), reason).red(),
+            ))?
+            .queue(Print("\n"))?;
+        }
+        for path in &self.cancelled {
+            out.queue(PrintStyledContent(
+                format!("  {}: cancelled", path_to_string(path)).dark_grey(),
+            ))?
+            .queue(Print("\n"))?;
+        }
+        for path in &self.skipped {
+            out.queue(PrintStyledContent(
+                format!("  {}: skipped", path_to_string(path)).dark_grey(),
+            ))?
+            .queue(Print("\n"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Describe how a command exited, for a command that didn't succeed.
+pub fn describe_exit(exit_code: Option<i32>) -> String {
+    match exit_code {
+        Some(code) => format!("exit code {}", code),
+        None => "terminated by a signal".to_owned(),
+    }
+}
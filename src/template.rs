@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use git2::Repository;
+
+use crate::command::status::read_upstream;
+use crate::config::Config;
+use crate::path::path_to_string;
+
+/// The names of the per-repository variables, as used in placeholders such as `{name}`.
+pub const VARIABLES: [&str; 7] = [
+    "name",
+    "path",
+    "parent",
+    "branch",
+    "upstream",
+    "remote_url",
+    "tags",
+];
+
+/// A command to run in each repository, before its placeholders are expanded.
+#[derive(Clone)]
+pub enum CommandTemplate {
+    /// A program and its arguments, each of which may contain placeholders.
+    Program { program: String, args: Vec<String> },
+    /// A script run with `<shell> -c`. Placeholders are replaced with shell-quoted values.
+    Shell { shell: String, script: String },
+}
+
+impl CommandTemplate {
+    pub fn program(program: &str, args: &[String]) -> Self {
+        CommandTemplate::Program {
+            program: program.to_owned(),
+            args: args.to_vec(),
+        }
+    }
+
+    /// The command line before expansion, e.g. for recognising the same command later.
+    pub fn words(&self) -> Vec<String> {
+        match self {
+            CommandTemplate::Program { program, args } => iter::once(program.clone())
+                .chain(args.iter().cloned())
+                .collect(),
+            CommandTemplate::Shell { shell, script } => {
+                vec![shell.clone(), "-c".to_owned(), script.clone()]
+            }
+        }
+    }
+}
+
+/// Values describing a repository, substituted for placeholders such as `{branch}` in a command
+/// and exported to it as environment variables such as `GITLASSO_BRANCH`.
+pub struct RepoVariables {
+    /// Values in the same order as `VARIABLES`.
+    values: [String; 7],
+}
+
+impl RepoVariables {
+    /// Collect the variables for each repository. Values that can't be read from git, e.g.
+    /// because the repository is missing, are left empty.
+    pub fn collect(config: &Config, paths: &[PathBuf]) -> HashMap<PathBuf, RepoVariables> {
+        paths
+            .iter()
+            .map(|path| {
+                let tags = config
+                    .repositories
+                    .iter()
+                    .find(|r| &r.path == path)
+                    .map(|r| r.tags.join(","))
+                    .unwrap_or_default();
+                let (branch, upstream, remote_url) = read_branch(path).unwrap_or_default();
+                let variables = RepoVariables {
+                    values: [
+                        path.file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                        path_to_string(path),
+                        path.parent().map(path_to_string).unwrap_or_default(),
+                        branch,
+                        upstream,
+                        remote_url,
+                        tags,
+                    ],
+                };
+                (path.clone(), variables)
+            })
+            .collect()
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        VARIABLES
+            .iter()
+            .position(|v| *v == name)
+            .map(|i| self.values[i].as_str())
+    }
+
+    /// Replace each known placeholder in `template`, quoting the values for a POSIX shell if
+    /// `quote` is set. Anything else in braces, such as `{}`, is left as it is, as is git's
+    /// `@{...}` revision syntax. A placeholder in double braces, e.g. `{{name}}`, is replaced
+    /// with the placeholder itself.
+    fn expand(&self, template: &str, quote: bool) -> String {
+        let mut expanded = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            let placeholder = &rest[start..];
+
+            if expanded.ends_with('@') {
+                expanded.push('{');
+                rest = &placeholder[1..];
+                continue;
+            }
+
+            let escaped = placeholder.strip_prefix("{{").and_then(|inner| {
+                let end = inner.find("}}")?;
+                self.get(&inner[..end])?;
+                Some(end + 4)
+            });
+            if let Some(end) = escaped {
+                expanded.push_str(&placeholder[1..end - 1]);
+                rest = &placeholder[end..];
+                continue;
+            }
+
+            let value = placeholder
+                .find('}')
+                .and_then(|end| Some((end, self.get(&placeholder[1..end])?)));
+            match value {
+                Some((end, value)) => {
+                    if quote {
+                        expanded.push_str(&shell_quote(value));
+                    } else {
+                        expanded.push_str(value);
+                    }
+                    rest = &placeholder[end + 1..];
+                }
+                None => {
+                    expanded.push('{');
+                    rest = &placeholder[1..];
+                }
+            }
+        }
+
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// Build a command to run in the repository, with placeholders expanded and the variables
+    /// exported.
+    pub fn command(&self, path: &Path, template: &CommandTemplate) -> Command {
+        let mut command = match template {
+            CommandTemplate::Program { program, args } => {
+                let mut command = Command::new(self.expand(program, false));
+                command.args(args.iter().map(|arg| self.expand(arg, false)));
+                command
+            }
+            CommandTemplate::Shell { shell, script } => {
+                let mut command = Command::new(shell);
+                command.arg("-c").arg(self.expand(script, true));
+                command
+            }
+        };
+        command.current_dir(path).envs(
+            VARIABLES
+                .iter()
+                .zip(&self.values)
+                .map(|(name, value)| (format!("GITLASSO_{}", name.to_ascii_uppercase()), value)),
+        );
+        command
+    }
+}
+
+/// Read the checked out branch, its upstream and the URL of the upstream's remote. Only the
+/// references are read, not the working tree, so this is quick enough to do for every command.
+fn read_branch(path: &Path) -> Option<(String, String, String)> {
+    let repo = Repository::open(path).ok()?;
+    let head = repo.head().ok()?;
+    let (upstream, remote_info) = read_upstream(&repo, &head);
+    Some((
+        head.shorthand().unwrap_or_default().to_owned(),
+        upstream.unwrap_or_default(),
+        remote_info.map(|r| r.url).unwrap_or_default(),
+    ))
+}
+
+/// Quote a value so that a POSIX shell treats it as a single word.
+fn shell_quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:@,+=%".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        return value.to_owned();
+    }
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> RepoVariables {
+        RepoVariables {
+            values: [
+                "api".to_owned(),
+                "/src/api".to_owned(),
+                "/src".to_owned(),
+                "main".to_owned(),
+                "refs/remotes/origin/main".to_owned(),
+                "git@example.com:me/api.git".to_owned(),
+                "backend,it's".to_owned(),
+            ],
+        }
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(variables().expand("{name}.tgz", false), "api.tgz");
+        assert_eq!(variables().expand("{parent}/{branch}", false), "/src/main");
+    }
+
+    #[test]
+    fn double_braces_escape_a_placeholder() {
+        assert_eq!(variables().expand("{{name}}", false), "{name}");
+        assert_eq!(variables().expand("{{name}} {name}", true), "{name} api");
+    }
+
+    #[test]
+    fn leaves_git_revision_syntax_alone() {
+        assert_eq!(
+            variables().expand("HEAD@{upstream}", false),
+            "HEAD@{upstream}"
+        );
+        assert_eq!(variables().expand("@{upstream}", true), "@{upstream}");
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        assert_eq!(variables().expand("{}", false), "{}");
+        assert_eq!(variables().expand("{unknown}", false), "{unknown}");
+        assert_eq!(variables().expand("{name", false), "{name");
+        assert_eq!(variables().expand("{{{name}", false), "{{api");
+    }
+
+    #[test]
+    fn quotes_values_for_the_shell() {
+        assert_eq!(
+            variables().expand("{remote_url}", true),
+            "git@example.com:me/api.git"
+        );
+        assert_eq!(variables().expand("{tags}", true), "'backend,it'\\''s'");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
@@ -81,7 +81,7 @@ pub fn queue_table(mut f: impl QueueableCommand, table: Table) -> Result<()> {
                     let mut content: String = span_chars.take(remaining_space - 1).collect();
                     content.push('…');
                     f.queue(PrintStyledContent(StyledContent::new(
-                        span.style().clone(),
+                        *span.style(),
                         content,
                     )))?;
                     pos += remaining_space;